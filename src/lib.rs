@@ -1,26 +1,77 @@
+use std::collections::HashSet;
 use std::fmt::{Display, Formatter};
-use std::ops::{Deref, DerefMut};
+use std::iter::once;
+use std::ops::{Deref, DerefMut, Range};
 use std::str::FromStr;
 
+use chrono::NaiveDate;
 use itertools::Itertools;
-use serde::{Deserialize, Deserializer, de::Error as DeError};
-use sqlx::{Database, Decode, FromRow, Postgres, Type, database::HasValueRef, error::BoxDynError, postgres::{PgHasArrayType, PgTypeInfo}};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error as DeError};
+use sqlx::{Database, Decode, Encode, FromRow, Postgres, Type, database::HasValueRef, encode::IsNull, error::BoxDynError, postgres::{PgArgumentBuffer, PgHasArrayType, PgTypeInfo, PgValueRef}};
 use split_first_char::SplitFirstChar;
-use strum::{Display, EnumString};
+use strum::{Display, EnumIter, EnumString, IntoEnumIterator};
+use utoipa::ToSchema;
+use utoipa::openapi::{ArrayBuilder, ObjectBuilder, Ref, RefOr, Schema, SchemaType};
 
-#[derive(FromRow, Debug, Clone)]
+pub const CHANGES_CHANNEL: &str = "courses_changed";
+
+pub const COURSE_CODE_PATTERN: &str = r"[A-Z][A-Z\d]\d{3}[A-Z]";
+
+lazy_static! {
+    static ref COURSE_CODE_PAT: Regex = Regex::new(&format!(r"\b{}\b", COURSE_CODE_PATTERN)).expect("failed to parse regex");
+}
+
+// Weights of what related courses have in common.
+const SHARED_CATEGORY_SCORE: u32 = 3;
+const SAME_DIFFICULTY_SCORE: u32 = 1;
+const SHARED_NAME_WORD_SCORE: u32 = 2;
+const PREREQUISITE_SCORE: u32 = 4;
+const SHARED_PREREQUISITE_SCORE: u32 = 2;
+
+// Shorter words are mostly ones like "och" and "of", which say nothing about the subject.
+const MIN_NAME_WORD_LENGTH: usize = 4;
+
+// Where course codes are mentioned in a text, like the prerequisites of a course.
+pub fn course_code_ranges(text: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    COURSE_CODE_PAT.find_iter(text).map(|m| m.range())
+}
+
+pub fn course_codes(text: &str) -> impl Iterator<Item = &str> {
+    course_code_ranges(text).map(|r| &text[r])
+}
+
+// `Display` gives the Swedish names used by the university, and this the English ones.
+pub trait English {
+    fn english(&self) -> String;
+}
+
+impl<T: English + ?Sized> English for &T {
+    fn english(&self) -> String {
+        (**self).english()
+    }
+}
+
+#[derive(FromRow, Serialize, ToSchema, Debug, Clone)]
 pub struct Course {
     pub code: String,
     pub name_se: String,
     pub name_en: Option<String>,
     pub url: String,
     pub points: f32,
+    pub credit_unit: CreditUnit,
     pub pace: Option<Pace>,
     pub prerequisites: Option<String>,
     pub register_info: Option<String>,
     pub modules: Option<String>,
     pub period_start: Option<i16>,
     pub period_end: Option<i16>,
+    // Monday of the first week and Sunday of the last week.
+    #[schema(value_type = Option<String>, format = Date)]
+    pub start_date: Option<NaiveDate>,
+    #[schema(value_type = Option<String>, format = Date)]
+    pub end_date: Option<NaiveDate>,
     pub site: Option<Site>,
     pub language: Option<Language>,
     pub difficulty: Difficulty,
@@ -28,6 +79,68 @@ pub struct Course {
     pub conduct: Option<String>,
 }
 
+impl Course {
+    // The words of both names in lower case.
+    pub fn name_words(&self) -> HashSet<String> {
+        once(&self.name_se)
+            .chain(&self.name_en)
+            .flat_map(|n| n.split(|c: char| !c.is_alphanumeric()))
+            .filter(|w| w.chars().count() >= MIN_NAME_WORD_LENGTH)
+            .map(str::to_lowercase)
+            .collect()
+    }
+
+    // The courses this one requires, as far as the prerequisites name them.
+    pub fn prerequisite_codes(&self) -> HashSet<&str> {
+        self.prerequisites.iter().flat_map(|p| course_codes(p)).collect()
+    }
+
+    // The periods a course runs in, where courses with `period_start` after `period_end`, like 4 to 1, wrap over the summer into the next academic year.
+    pub fn study_periods(&self) -> Vec<i16> {
+        match (self.period_start, self.period_end) {
+            (Some(start), Some(end)) if start <= end => (start..=end).collect(),
+            (Some(start), Some(end)) => (start..=4).chain(1..=end).collect(),
+            (Some(period), None) | (None, Some(period)) => vec![period],
+            (None, None) => Vec::new(),
+        }
+    }
+
+    // How much two courses have in common, where 0 means nothing but perhaps the difficulty.
+    pub fn relatedness(&self, other: &Course) -> u32 {
+        let categories = self.categories.iter().filter(|c| other.categories.contains(c)).count() as u32;
+        let name_words = self.name_words().intersection(&other.name_words()).count() as u32;
+        let own_prerequisites = self.prerequisite_codes();
+        let other_prerequisites = other.prerequisite_codes();
+        let prerequisites = own_prerequisites.contains(other.code.as_str()) || other_prerequisites.contains(self.code.as_str());
+        let shared_prerequisites = own_prerequisites.intersection(&other_prerequisites).count() as u32;
+
+        let score = categories * SHARED_CATEGORY_SCORE
+            + name_words * SHARED_NAME_WORD_SCORE
+            + prerequisites as u32 * PREREQUISITE_SCORE
+            + shared_prerequisites * SHARED_PREREQUISITE_SCORE;
+
+        // Most courses share a difficulty, so it only separates courses that are already related.
+        match score {
+            0 => 0,
+            _ if self.difficulty == other.difficulty => score + SAME_DIFFICULTY_SCORE,
+            _ => score
+        }
+    }
+
+    // The `limit` most related of `candidates`, most related first.
+    pub fn related<'a>(&self, candidates: &'a [Course], limit: usize) -> Vec<&'a Course> {
+        candidates
+            .iter()
+            .filter(|c| c.code != self.code)
+            .map(|c| (self.relatedness(c), c))
+            .filter(|&(score, _)| score > 0)
+            .sorted_by(|(a_score, a), (b_score, b)| b_score.cmp(a_score).then(a.code.cmp(&b.code)))
+            .take(limit)
+            .map(|(_, c)| c)
+            .collect()
+    }
+}
+
 #[derive(Type, Default, Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Percentage(i8);
 
@@ -69,7 +182,47 @@ impl Display for Percentage {
     }
 }
 
-#[derive(Type, Debug, Clone, Copy, Hash, Eq, PartialEq, Display, EnumString)]
+#[derive(Type, Debug, Clone, Copy, Hash, PartialEq, Eq, Display, EnumString, EnumIter)]
+#[sqlx(type_name = "credit_unit_enum")]
+pub enum CreditUnit {
+    #[strum(serialize = "högskolepoäng", ascii_case_insensitive)]
+    HigherEducation,
+    #[strum(serialize = "förutbildningspoäng", ascii_case_insensitive)]
+    Preparatory,
+}
+
+impl CreditUnit {
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::HigherEducation => "hp",
+            Self::Preparatory => "fup",
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CreditUnit {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        match String::deserialize(deserializer)?.as_str() {
+            "hp" => Ok(Self::HigherEducation),
+            "fup" => Ok(Self::Preparatory),
+            other => Err(DeError::unknown_variant(other, &["hp", "fup"]))
+        }
+    }
+}
+
+impl Serialize for CreditUnit {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl PgHasArrayType for CreditUnit {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_credit_unit_enum")
+    }
+}
+
+#[derive(Type, Debug, Clone, Copy, Hash, Eq, PartialEq, Display, EnumString, EnumIter)]
 pub enum Time {
     #[strum(serialize = "Dagtid", ascii_case_insensitive)]
     Day,
@@ -79,8 +232,35 @@ pub enum Time {
     Mixed,
 }
 
-#[derive(Type, Debug, Clone, Copy, Hash, Eq, PartialEq)]
-#[sqlx(type_name = "pace_enum")]
+impl Time {
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Day => "d",
+            Self::Weekend => "w",
+            Self::Mixed => "m",
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Time {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        match String::deserialize(deserializer)?.as_str() {
+            "d" => Ok(Self::Day),
+            "w" => Ok(Self::Weekend),
+            "m" => Ok(Self::Mixed),
+            other => Err(DeError::unknown_variant(other, &["d", "w", "m"]))
+        }
+    }
+}
+
+impl Serialize for Time {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(self.code())
+    }
+}
+
+// Stored as the enum labels `Day50`, `Weekend25` and so on, rather than as a record.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
 pub struct Pace {
     time: Time,
     percentage: Percentage,
@@ -107,10 +287,64 @@ impl FromStr for Pace {
     }
 }
 
+impl Pace {
+    pub fn new(time: Time, percentage: Percentage) -> Self {
+        Self { time, percentage }
+    }
+
+    pub fn time(self) -> Time {
+        self.time
+    }
+
+    pub fn percentage(self) -> Percentage {
+        self.percentage
+    }
+
+    pub fn code(self) -> String {
+        format!("{}{}", self.time.code(), *self.percentage)
+    }
+
+    pub fn label(self) -> String {
+        format!("{:?}{}", self.time, *self.percentage)
+    }
+
+    fn from_label(label: &str) -> Option<Self> {
+        let (time, percentage) = label.split_at(label.find(|c: char| c.is_ascii_digit())?);
+
+        let time = Time::iter().find(|t| format!("{:?}", t) == time)?;
+        let percentage = percentage
+            .parse::<i8>()
+            .ok()?
+            .try_into()
+            .ok()?;
+
+        Some(Self { time, percentage })
+    }
+}
+
+impl Type<Postgres> for Pace {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("pace_enum")
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for Pace {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        let label = <&str as Decode<Postgres>>::decode(value)?;
+        Self::from_label(label).ok_or_else(|| format!("invalid pace {}", label).into())
+    }
+}
+
+impl Encode<'_, Postgres> for Pace {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> IsNull {
+        <String as Encode<Postgres>>::encode(self.label(), buf)
+    }
+}
+
 impl<'de> Deserialize<'de> for Pace {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
         fn parse<'de>(deserializer: impl Deserializer<'de>) -> Option<Pace> {
-            let s = <&str as Deserialize>::deserialize(deserializer).ok()?;
+            let s = String::deserialize(deserializer).ok()?;
             let (time, percentage) = s.split_first_char()?;
 
             let time = match time {
@@ -132,6 +366,28 @@ impl<'de> Deserialize<'de> for Pace {
     }
 }
 
+impl Serialize for Pace {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(&self.code())
+    }
+}
+
+impl<'s> ToSchema<'s> for Pace {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        ("Pace", ObjectBuilder::new()
+            .schema_type(SchemaType::String)
+            .pattern(Some(r"^[dwm]\d{1,3}$"))
+            .description(Some(format!(
+                "Teaching time followed by the percentage of full time studies, such as `d50`. Times are {}.",
+                Time::iter()
+                    .map(|t| format!("`{}`: {}", t.code(), t))
+                    .join(", ")
+            )))
+            .example(Some("d50".into()))
+            .into())
+    }
+}
+
 impl PgHasArrayType for Pace {
     fn array_type_info() -> PgTypeInfo {
         PgTypeInfo::with_name("_pace_enum")
@@ -144,7 +400,13 @@ impl Display for Pace {
     }
 }
 
-#[derive(Type, Debug, Clone, Copy, Hash, PartialEq, Eq, Display, EnumString)]
+impl English for Pace {
+    fn english(&self) -> String {
+        format!("{} {}", self.time.english(), self.percentage)
+    }
+}
+
+#[derive(Type, Debug, Clone, Copy, Hash, PartialEq, Eq, Display, EnumString, EnumIter)]
 #[sqlx(type_name = "site_enum")]
 pub enum Site {
     #[strum(serialize = "Stockholm")]
@@ -161,9 +423,22 @@ pub enum Site {
     LocationIndependent,
 }
 
+impl Site {
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Stockholm => "st",
+            Self::Pitea => "pt",
+            Self::Skelleftea => "sk",
+            Self::Lulea => "lu",
+            Self::Kiruna => "kr",
+            Self::LocationIndependent => "li",
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for Site {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
-        match Deserialize::deserialize(deserializer)? {
+        match String::deserialize(deserializer)?.as_str() {
             "st" => Ok(Self::Stockholm),
             "pt" => Ok(Self::Pitea),
             "sk" => Ok(Self::Skelleftea),
@@ -175,13 +450,19 @@ impl<'de> Deserialize<'de> for Site {
     }
 }
 
+impl Serialize for Site {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(self.code())
+    }
+}
+
 impl PgHasArrayType for Site {
     fn array_type_info() -> PgTypeInfo {
         PgTypeInfo::with_name("_site_enum")
     }
 }
 
-#[derive(Type, Debug, Clone, Copy, Hash, PartialEq, Eq, Display, EnumString)]
+#[derive(Type, Debug, Clone, Copy, Hash, PartialEq, Eq, Display, EnumString, EnumIter)]
 #[sqlx(type_name = "language_enum")]
 pub enum Language {
     #[strum(serialize = "Svenska")]
@@ -190,9 +471,18 @@ pub enum Language {
     English,
 }
 
+impl Language {
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Swedish => "sv",
+            Self::English => "en",
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for Language {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
-        match Deserialize::deserialize(deserializer)? {
+        match String::deserialize(deserializer)?.as_str() {
             "sv" => Ok(Self::Swedish),
             "en" => Ok(Self::English),
             other => Err(DeError::unknown_variant(other, &["sv", "en"]))
@@ -200,13 +490,19 @@ impl<'de> Deserialize<'de> for Language {
     }
 }
 
+impl Serialize for Language {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(self.code())
+    }
+}
+
 impl PgHasArrayType for Language {
     fn array_type_info() -> PgTypeInfo {
         PgTypeInfo::with_name("_language_enum")
     }
 }
 
-#[derive(Type, Debug, Clone, Copy, Hash, PartialEq, Eq, Display, EnumString)]
+#[derive(Type, Debug, Clone, Copy, Hash, PartialEq, Eq, Display, EnumString, EnumIter)]
 #[sqlx(type_name = "difficulty_enum")]
 pub enum Difficulty {
     #[strum(serialize = "Förberedande nivå")]
@@ -223,9 +519,22 @@ pub enum Difficulty {
     IntroductoryUndergraduate,
 }
 
+impl Difficulty {
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Preparatory => "pr",
+            Self::Undergraduate => "ug",
+            Self::Advanced => "ad",
+            Self::ContinuationUndergraduate => "cu",
+            Self::ContinuationAdvanced => "ca",
+            Self::IntroductoryUndergraduate => "iu",
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for Difficulty {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
-        match Deserialize::deserialize(deserializer)? {
+        match String::deserialize(deserializer)?.as_str() {
             "pr" => Ok(Self::Preparatory),
             "ug" => Ok(Self::Undergraduate),
             "ad" => Ok(Self::Advanced),
@@ -237,13 +546,19 @@ impl<'de> Deserialize<'de> for Difficulty {
     }
 }
 
+impl Serialize for Difficulty {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(self.code())
+    }
+}
+
 impl PgHasArrayType for Difficulty {
     fn array_type_info() -> PgTypeInfo {
         PgTypeInfo::with_name("_difficulty_enum")
     }
 }
 
-#[derive(Type, Debug, Clone, Copy, Hash, PartialEq, Eq, Display, EnumString)]
+#[derive(Type, Debug, Clone, Copy, Hash, PartialEq, Eq, Display, EnumString, EnumIter)]
 #[sqlx(type_name = "category_enum")]
 pub enum Category {
     #[strum(serialize = "Data och IT")]
@@ -280,9 +595,32 @@ pub enum Category {
     Behavioral,
 }
 
+impl Category {
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Data => "dat",
+            Self::Economy => "eco",
+            Self::Environment => "env",
+            Self::Health => "hth",
+            Self::Law => "law",
+            Self::Education => "edu",
+            Self::Music => "mus",
+            Self::Social => "soc",
+            Self::Technology => "tec",
+            Self::Media => "med",
+            Self::Interdisciplinary => "ind",
+            Self::Language => "lng",
+            Self::Mathematics => "mat",
+            Self::Information => "inf",
+            Self::Humanities => "hum",
+            Self::Behavioral => "bhv",
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for Category {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
-        match Deserialize::deserialize(deserializer)? {
+        match String::deserialize(deserializer)?.as_str() {
             "dat" => Ok(Self::Data),
             "eco" => Ok(Self::Economy),
             "env" => Ok(Self::Environment),
@@ -304,7 +642,13 @@ impl<'de> Deserialize<'de> for Category {
     }
 }
 
-#[derive(Debug, Default, Clone, Hash, PartialEq, Eq)]
+impl Serialize for Category {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(self.code())
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone, Hash, PartialEq, Eq)]
 pub struct Categories(Vec<Category>);
 
 impl Deref for Categories {
@@ -342,6 +686,14 @@ impl From<Vec<Category>> for Categories {
     }
 }
 
+impl<'s> ToSchema<'s> for Categories {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        ("Categories", ArrayBuilder::new()
+            .items(RefOr::Ref(Ref::from_schema_name("Category")))
+            .into())
+    }
+}
+
 impl Type<Postgres> for Categories {
     fn type_info() -> <Postgres as Database>::TypeInfo {
         PgTypeInfo::with_name("_category_enum")
@@ -353,3 +705,181 @@ impl<'r> Decode<'r, Postgres> for Categories {
         Ok(Self(Vec::decode(value)?))
     }
 }
+
+// The enums are serialized as their short codes, so their schemas list those instead of the variant names.
+macro_rules! code_schema {
+    ($($name:ident),*) => {
+        $(
+            impl<'s> ToSchema<'s> for $name {
+                fn schema() -> (&'s str, RefOr<Schema>) {
+                    (stringify!($name), ObjectBuilder::new()
+                        .schema_type(SchemaType::String)
+                        .enum_values(Some($name::iter().map($name::code)))
+                        .description(Some($name::iter()
+                            .map(|v| format!("`{}`: {}", v.code(), v))
+                            .join(", ")))
+                        .into())
+                }
+            }
+        )*
+    };
+}
+
+code_schema!(CreditUnit, Time, Site, Language, Difficulty, Category);
+
+macro_rules! english {
+    ($name:ident { $($variant:ident => $label:literal),* $(,)? }) => {
+        impl English for $name {
+            fn english(&self) -> String {
+                match self {
+                    $(Self::$variant => $label,)*
+                }.to_owned()
+            }
+        }
+    };
+}
+
+english!(CreditUnit {
+    HigherEducation => "credits",
+    Preparatory => "preparatory credits",
+});
+
+english!(Time {
+    Day => "Daytime",
+    Weekend => "Weekends",
+    Mixed => "Mixed teaching times",
+});
+
+english!(Site {
+    Stockholm => "Stockholm",
+    Pitea => "Piteå",
+    Skelleftea => "Skellefteå",
+    Lulea => "Luleå",
+    Kiruna => "Kiruna",
+    LocationIndependent => "Location independent",
+});
+
+english!(Language {
+    Swedish => "Swedish",
+    English => "English",
+});
+
+english!(Difficulty {
+    Preparatory => "Preparatory level",
+    Undergraduate => "First cycle",
+    Advanced => "Second cycle",
+    ContinuationUndergraduate => "Continuation course, first cycle",
+    ContinuationAdvanced => "Continuation course, second cycle",
+    IntroductoryUndergraduate => "Introductory course, first cycle",
+});
+
+english!(Category {
+    Data => "Computer science and IT",
+    Economy => "Economics, organisation and business",
+    Environment => "Energy, environment and sustainable development",
+    Health => "Health, care and sports",
+    Law => "Law",
+    Education => "Teaching and education",
+    Music => "Music and theatre",
+    Social => "Social sciences",
+    Technology => "Technology",
+    Media => "Media",
+    Interdisciplinary => "Interdisciplinary studies",
+    Language => "Languages",
+    Mathematics => "Mathematics and natural sciences",
+    Information => "Information and communication",
+    Humanities => "Humanities",
+    Behavioral => "Behavioural sciences",
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn course(code: &str, name_se: &str, name_en: Option<&str>, categories: Vec<Category>, difficulty: Difficulty, prerequisites: Option<&str>) -> Course {
+        Course {
+            code: code.to_owned(),
+            name_se: name_se.to_owned(),
+            name_en: name_en.map(str::to_owned),
+            url: String::new(),
+            points: 7.5,
+            credit_unit: CreditUnit::HigherEducation,
+            pace: None,
+            prerequisites: prerequisites.map(str::to_owned),
+            register_info: None,
+            modules: None,
+            period_start: None,
+            period_end: None,
+            start_date: None,
+            end_date: None,
+            site: None,
+            language: None,
+            difficulty,
+            categories: Categories::from(categories),
+            conduct: None,
+        }
+    }
+
+    fn programming() -> Course {
+        course("D0015E", "Objektorienterad programmering och design", Some("Object oriented programming"), vec![Category::Data], Difficulty::Undergraduate, None)
+    }
+
+    fn candidates() -> Vec<Course> {
+        vec![
+            course("Y0001Y", "Databaser", None, vec![Category::Data], Difficulty::Advanced, None),
+            course("H0001H", "Hälsa och idrott", None, vec![Category::Health], Difficulty::Undergraduate, None),
+            course("E0001E", "Programmering i Python", Some("Programming in Python"), vec![Category::Economy], Difficulty::Advanced, None),
+            programming(),
+            course("D0016E", "Datastrukturer", None, vec![Category::Data], Difficulty::Undergraduate, Some("Godkänd kurs D0015E.")),
+            course("X0001X", "Statistik", None, vec![Category::Data], Difficulty::Advanced, None),
+        ]
+    }
+
+    fn codes(courses: Vec<&Course>) -> Vec<&str> {
+        courses.into_iter().map(|c| c.code.as_str()).collect()
+    }
+
+    #[test]
+    fn name_words() {
+        let words = programming().name_words();
+        assert_eq!(words, ["objektorienterad", "programmering", "design", "object", "oriented", "programming"].map(str::to_owned).into());
+    }
+
+    #[test]
+    fn relatedness() {
+        let programming = programming();
+        let candidates = candidates();
+        let score = |code: &str| programming.relatedness(candidates.iter().find(|c| c.code == code).unwrap());
+
+        // A shared category, a prerequisite and the same difficulty.
+        assert_eq!(score("D0016E"), SHARED_CATEGORY_SCORE + PREREQUISITE_SCORE + SAME_DIFFICULTY_SCORE);
+        // Two shared name words, as "och" and "i" are too short.
+        assert_eq!(score("E0001E"), 2 * SHARED_NAME_WORD_SCORE);
+        assert_eq!(score("X0001X"), SHARED_CATEGORY_SCORE);
+        // The same difficulty alone doesn't make courses related.
+        assert_eq!(score("H0001H"), 0);
+
+        let later = course("D0017E", "Algoritmer", None, vec![], Difficulty::Advanced, Some("D0015E och D0016E"));
+        assert_eq!(later.relatedness(&candidates[4]), PREREQUISITE_SCORE + SHARED_PREREQUISITE_SCORE);
+        assert_eq!(candidates[4].relatedness(&later), later.relatedness(&candidates[4]));
+    }
+
+    #[test]
+    fn related_order() {
+        let candidates = candidates();
+
+        // Most related first, ties by code, and neither unrelated courses nor the course itself.
+        assert_eq!(codes(programming().related(&candidates, 10)), ["D0016E", "E0001E", "X0001X", "Y0001Y"]);
+        assert_eq!(codes(programming().related(&candidates, 2)), ["D0016E", "E0001E"]);
+        assert!(programming().related(&candidates, 0).is_empty());
+    }
+
+    #[test]
+    fn never_related_to_itself() {
+        let candidates = candidates();
+
+        for course in &candidates {
+            assert!(course.related(&candidates, candidates.len()).iter().all(|c| c.code != course.code), "{}", course.code);
+        }
+    }
+}
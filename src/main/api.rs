@@ -0,0 +1,173 @@
+use actix_web::{get, HttpResponse, post, Responder, web};
+use actix_web::error::InternalError;
+use actix_web::web::QueryConfig;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use courselib::Course;
+
+use super::course::by_code;
+use super::saved::{load, save, SavedSearch, validate};
+use super::search::{Facets, prepare, search as run_search, SearchQuery, SearchResults, TextQuery};
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ErrorResponse {
+    error: String,
+}
+
+impl ErrorResponse {
+    fn new(error: impl ToString) -> Self {
+        Self { error: error.to_string() }
+    }
+}
+
+// Query strings which can't be read get the same kind of body as queries which are invalid in other ways.
+pub(crate) fn query_config() -> QueryConfig {
+    QueryConfig::default().error_handler(|e, _| {
+        let response = HttpResponse::BadRequest().json(ErrorResponse::new(&e));
+        InternalError::from_response(e, response).into()
+    })
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct SearchResponse {
+    /// Number of matching courses on all pages.
+    total: i64,
+    courses: Vec<Course>,
+    /// Number of matching courses per value, each counted without the filters on that field.
+    facets: Facets,
+    /// Cursor for the following page, if there is one.
+    next: Option<String>,
+    /// Cursor for the preceding page, if there is one.
+    previous: Option<String>,
+}
+
+impl From<SearchResults> for SearchResponse {
+    fn from(value: SearchResults) -> Self {
+        Self {
+            total: value.total,
+            courses: value.courses,
+            facets: value.facets,
+            next: value.next.map(|c| c.to_string()),
+            previous: value.previous.map(|c| c.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct SavedSearchResponse {
+    #[serde(flatten)]
+    saved: SavedSearch,
+    #[serde(flatten)]
+    results: SearchResponse,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/courses",
+    params(SearchQuery, TextQuery),
+    responses(
+        (status = 200, description = "One page of matching courses", body = SearchResponse),
+        (status = 400, description = "The query is invalid", body = ErrorResponse),
+    ),
+)]
+#[get("/courses")]
+async fn search(query: web::Query<SearchQuery>, text: web::Query<TextQuery>) -> impl Responder {
+    let (conditions, pagination) = match prepare(query.into_inner(), text.into_inner()).await {
+        Ok(prepared) => prepared,
+        Err(e) => return HttpResponse::BadRequest().json(ErrorResponse::new(e))
+    };
+
+    match run_search(&conditions, &pagination).await {
+        Ok(results) => HttpResponse::Ok().json(SearchResponse::from(results)),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse::new("internal server error"))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/courses/{code}",
+    params(("code" = String, Path, description = "Course code, in any case")),
+    responses(
+        (status = 200, description = "The course", body = Course),
+        (status = 404, description = "There is no course with the code", body = ErrorResponse),
+    ),
+)]
+#[get("/courses/{code}")]
+async fn course(code: web::Path<String>) -> impl Responder {
+    match by_code(&code).await {
+        Ok(Some(course)) => HttpResponse::Ok().json(course),
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse::new(format!("no course with code {}", code.to_uppercase()))),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse::new("internal server error"))
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/saved",
+    params(SearchQuery, TextQuery),
+    responses(
+        (status = 201, description = "The saved search, which is the same for equal queries", body = SavedSearch),
+        (status = 400, description = "The query is invalid", body = ErrorResponse),
+    ),
+)]
+#[post("/saved")]
+async fn create_saved(query: web::Query<SearchQuery>, text: web::Query<TextQuery>) -> impl Responder {
+    let query = match validate(query.into_inner(), text.into_inner()).await {
+        Ok(query) => query,
+        Err(e) => return HttpResponse::BadRequest().json(ErrorResponse::new(e))
+    };
+
+    match save(&query).await {
+        Ok(saved) => HttpResponse::Created().json(saved),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse::new("internal server error"))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/saved/{slug}",
+    params(
+        ("slug" = String, Path, description = "Slug of the saved search"),
+        ("cursor" = Option<String>, Query, description = "Continues from the `next` or `previous` cursor of an earlier response."),
+        ("per_page" = Option<u32>, Query, description = "Results per page, at most 100."),
+    ),
+    responses(
+        (status = 200, description = "The saved search and one page of its results", body = SavedSearchResponse),
+        (status = 400, description = "The paging parameters are invalid", body = ErrorResponse),
+        (status = 404, description = "There is no saved search with the slug", body = ErrorResponse),
+    ),
+)]
+#[get("/saved/{slug}")]
+async fn saved_search(slug: web::Path<String>, query: web::Query<SearchQuery>) -> impl Responder {
+    let saved = match load(&slug).await {
+        Ok(Some(saved)) => saved,
+        Ok(None) => return HttpResponse::NotFound().json(ErrorResponse::new(format!("no saved search with slug {}", slug))),
+        Err(e) => {
+            eprintln!("{}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse::new("internal server error"))
+        }
+    };
+
+    let (conditions, pagination) = match prepare(query.into_inner(), TextQuery { q: Some(saved.query.clone()) }).await {
+        Ok(prepared) => prepared,
+        Err(e) => return HttpResponse::BadRequest().json(ErrorResponse::new(e))
+    };
+
+    match run_search(&conditions, &pagination).await {
+        Ok(results) => HttpResponse::Ok().json(SavedSearchResponse { saved, results: results.into() }),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse::new("internal server error"))
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use actix_web::{get, HttpResponse, Responder, rt, web};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sqlx::{Error as SqlxError, query_as};
+use sqlx::postgres::PgListener;
+use tokio::time::sleep;
+use utoipa::{IntoParams, ToSchema};
+
+use courselib::CHANGES_CHANNEL;
+
+use super::CONNECTION;
+use super::cache::invalidate;
+
+const SUGGESTIONS: usize = 10;
+const MAX_SUGGESTIONS: usize = 50;
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+// The whole index is swapped at once, so a rebuild never blocks or confuses a lookup in progress.
+lazy_static! {
+    static ref INDEX: RwLock<Arc<Index>> = RwLock::new(Arc::default());
+}
+
+#[derive(Serialize, ToSchema, Debug, Clone)]
+pub(crate) struct Suggestion {
+    pub(crate) code: String,
+    pub(crate) name_se: String,
+    pub(crate) name_en: Option<String>,
+}
+
+// Names are kept in lower case next to the originals so that a lookup doesn't allocate for every course.
+struct Entry {
+    suggestion: Suggestion,
+    code: String,
+    name_se: String,
+    name_en: Option<String>,
+}
+
+#[derive(Default)]
+struct Index(Vec<Entry>);
+
+// Lower ranks are better matches.
+fn rank(name: &str, prefix: &str) -> Option<u8> {
+    if name.starts_with(prefix) {
+        Some(1)
+    } else if name.split_whitespace().any(|w| w.starts_with(prefix)) {
+        Some(2)
+    } else if name.contains(prefix) {
+        Some(3)
+    } else {
+        None
+    }
+}
+
+impl Index {
+    fn new(suggestions: Vec<Suggestion>) -> Self {
+        Self(suggestions
+            .into_iter()
+            .map(|s| Entry {
+                code: s.code.to_lowercase(),
+                name_se: s.name_se.to_lowercase(),
+                name_en: s.name_en.as_deref().map(str::to_lowercase),
+                suggestion: s,
+            })
+            .collect())
+    }
+
+    fn lookup(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        let prefix = prefix.trim().to_lowercase();
+        if prefix.is_empty() {
+            return Vec::new();
+        }
+
+        let mut matches = self.0
+            .iter()
+            .filter_map(|e| {
+                let rank = if e.code.starts_with(&prefix) {
+                    Some(0)
+                } else {
+                    [Some(&e.name_se), e.name_en.as_ref()]
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|n| rank(n, &prefix))
+                        .min()
+                };
+                rank.map(|r| (r, e))
+            })
+            .collect::<Vec<_>>();
+
+        // Shorter names are closer to what has been typed so far.
+        matches.sort_by(|(a_rank, a), (b_rank, b)| a_rank
+            .cmp(b_rank)
+            .then(a.name_se.len().cmp(&b.name_se.len()))
+            .then(a.code.cmp(&b.code)));
+
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, e)| e.suggestion.clone())
+            .collect()
+    }
+}
+
+async fn rebuild() -> Result<(), SqlxError> {
+    let suggestions = query_as!(
+        Suggestion,
+        "SELECT code, name_se, name_en FROM courses"
+    )
+        .fetch_all(CONNECTION.get().unwrap())
+        .await?;
+
+    *INDEX.write().unwrap() = Arc::new(Index::new(suggestions));
+    // Rendered pages link course codes by the index, so they are forgotten after it is rebuilt.
+    invalidate();
+
+    Ok(())
+}
+
+async fn listen() -> Result<(), SqlxError> {
+    let mut listener = PgListener::connect_with(CONNECTION.get().unwrap()).await?;
+    listener.listen(CHANGES_CHANNEL).await?;
+
+    // Notifications sent while the listener was disconnected are lost, so the index is rebuilt after every (re)connect.
+    rebuild().await?;
+
+    loop {
+        listener.recv().await?;
+        rebuild().await?;
+    }
+}
+
+// Keeps the index and the page cache up to date for as long as the server runs.
+pub(crate) fn spawn_updater() {
+    rt::spawn(async {
+        loop {
+            if let Err(e) = listen().await {
+                eprintln!("{}", e);
+            }
+            sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+// The course with exactly this code, if there is one.
+pub(crate) fn find(code: &str) -> Option<Suggestion> {
+    let index = INDEX.read().unwrap().clone();
+    let code = code.to_lowercase();
+    index.0.iter().find(|e| e.code == code).map(|e| e.suggestion.clone())
+}
+
+pub(crate) fn suggestions(prefix: &str, limit: usize) -> Vec<Suggestion> {
+    let index = INDEX.read().unwrap().clone();
+    index.lookup(prefix, limit)
+}
+
+#[derive(Deserialize, IntoParams, Debug, Clone)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct AutocompleteQuery {
+    /// What has been typed so far, matched against codes and the start of words in the names.
+    prefix: String,
+    /// Number of suggestions, at most 50.
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/autocomplete",
+    params(AutocompleteQuery),
+    responses(
+        (status = 200, description = "The best matches first", body = Vec<Suggestion>),
+        (status = 400, description = "The prefix is missing or the limit is not a number", body = ErrorResponse),
+    ),
+)]
+#[get("/autocomplete")]
+async fn suggest(query: web::Query<AutocompleteQuery>) -> impl Responder {
+    let limit = query.limit.unwrap_or(SUGGESTIONS).clamp(1, MAX_SUGGESTIONS);
+
+    HttpResponse::Ok().json(suggestions(&query.prefix, limit))
+}
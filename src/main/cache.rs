@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::{HttpRequest, HttpResponse, HttpResponseBuilder};
+use actix_web::http::header::{CACHE_CONTROL, EntityTag, ETag, Header, HttpDate, IF_NONE_MATCH, IfModifiedSince, IfNoneMatch, LastModified, VARY};
+use actix_web::web::Bytes;
+use lazy_static::lazy_static;
+
+use super::format::{Format, VARY_HEADERS};
+use super::locale::Locale;
+use super::saved::{digits, hash};
+
+// Pages are only rendered from the course data, so they stay the same until populate runs again and the server is notified.
+// The last modified time is when the server last saw the data change, which is also when it (re)connected to listen for changes.
+
+// Beyond this many pages new ones are rendered for every request, which keeps a crawler from filling up the memory.
+const MAX_PAGES: usize = 10_000;
+// Query string parameters which don't change the data of a page, other ones make it uncacheable.
+const CACHEABLE_PARAMETERS: [&str; 2] = ["lang", "format"];
+
+lazy_static! {
+    static ref CACHE: RwLock<Cache> = RwLock::new(Cache::new());
+}
+
+// The locale, the format and the link to switch language, which holds the path and the rest of the query string.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub(crate) struct Key(Locale, Format, String);
+
+impl Key {
+    // Requests with parameters the page doesn't depend on are left out, since there's no end to them.
+    pub(crate) fn of(request: &HttpRequest, locale: Locale, format: Format) -> Option<Self> {
+        serde_urlencoded::from_str::<Vec<(String, String)>>(request.query_string())
+            .ok()?
+            .iter()
+            .all(|(key, _)| CACHEABLE_PARAMETERS.contains(&key.as_str()))
+            .then(|| Self(locale, format, locale.switch_link(request)))
+    }
+}
+
+struct Cache {
+    changed: SystemTime,
+    pages: HashMap<Key, Arc<Page>>,
+}
+
+impl Cache {
+    fn new() -> Self {
+        Self {
+            changed: SystemTime::now(),
+            pages: HashMap::new(),
+        }
+    }
+}
+
+// In whole seconds, as HTTP dates have no finer precision.
+fn date(time: SystemTime) -> HttpDate {
+    let seconds = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    HttpDate::from(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+// Forgets all pages, for when the course data has changed.
+pub(crate) fn invalidate() {
+    *CACHE.write().unwrap() = Cache::new();
+}
+
+// When the data of pages rendered now was last changed.
+pub(crate) fn changed() -> SystemTime {
+    CACHE.read().unwrap().changed
+}
+
+pub(crate) fn get(key: &Key) -> Option<Arc<Page>> {
+    CACHE.read().unwrap().pages.get(key).cloned()
+}
+
+// Pages rendered from data that has changed since are thrown away.
+pub(crate) fn insert(key: Key, page: Arc<Page>) {
+    let mut cache = CACHE.write().unwrap();
+    if cache.changed == page.changed && cache.pages.len() < MAX_PAGES {
+        cache.pages.insert(key, page);
+    }
+}
+
+// Whether the client already has what would be sent, as told by `If-None-Match`, or else by `If-Modified-Since`.
+fn fresh(request: &HttpRequest, tag: Option<&EntityTag>, changed: SystemTime) -> bool {
+    if request.headers().contains_key(IF_NONE_MATCH) {
+        return match (IfNoneMatch::parse(request), tag) {
+            (Ok(IfNoneMatch::Any), _) => true,
+            (Ok(IfNoneMatch::Items(tags)), Some(tag)) => tags.iter().any(|t| t.weak_eq(tag)),
+            _ => false,
+        }
+    }
+
+    IfModifiedSince::parse(request).is_ok_and(|IfModifiedSince(since)| date(changed) <= since)
+}
+
+fn validate(response: &mut HttpResponseBuilder, tag: Option<&EntityTag>, changed: SystemTime) {
+    if let Some(tag) = tag {
+        response.insert_header(ETag(tag.clone()));
+    }
+    response
+        .insert_header(LastModified(date(changed)))
+        // Pages may be stored, but must be checked for changes before they are used again.
+        .insert_header((CACHE_CONTROL, "no-cache"))
+        .insert_header((VARY, VARY_HEADERS));
+}
+
+// Answers without rendering the page, when the client has one from after the last change.
+// Only for pages which are known to exist, as `If-None-Match: *` matches any page.
+pub(crate) fn not_modified(request: &HttpRequest) -> Option<HttpResponse> {
+    let changed = changed();
+    fresh(request, None, changed).then(|| {
+        let mut response = HttpResponse::NotModified();
+        validate(&mut response, None, changed);
+        response.finish()
+    })
+}
+
+#[derive(Debug)]
+pub(crate) struct Page {
+    format: Format,
+    body: Bytes,
+    tag: EntityTag,
+    changed: SystemTime,
+}
+
+impl Page {
+    // `changed` should be taken before the data of the page is looked up.
+    pub(crate) fn new(format: Format, body: String, changed: SystemTime) -> Arc<Self> {
+        Arc::new(Self {
+            format,
+            tag: EntityTag::new_strong(digits(hash(&body))),
+            body: Bytes::from(body),
+            changed,
+        })
+    }
+
+    pub(crate) fn respond(&self, request: &HttpRequest) -> HttpResponse {
+        let fresh = fresh(request, Some(&self.tag), self.changed);
+        let mut response = if fresh { HttpResponse::NotModified() } else { self.format.respond() };
+        validate(&mut response, Some(&self.tag), self.changed);
+
+        if fresh {
+            response.finish()
+        } else {
+            response.body(self.body.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH};
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    const CHANGED: &str = "Sun, 06 Oct 2024 12:00:00 GMT";
+
+    fn changed_at() -> SystemTime {
+        // Part of a second later, which the date leaves out.
+        SystemTime::from(CHANGED.parse::<HttpDate>().unwrap()) + Duration::from_millis(500)
+    }
+
+    fn tag() -> EntityTag {
+        EntityTag::new_strong("3d7257heikzbw".to_owned())
+    }
+
+    #[test]
+    fn matching_tag() {
+        let request = TestRequest::default().insert_header((IF_NONE_MATCH, "\"other\", W/\"3d7257heikzbw\"")).to_http_request();
+        assert!(fresh(&request, Some(&tag()), changed_at()));
+
+        let request = TestRequest::default().insert_header((IF_NONE_MATCH, "\"other\"")).to_http_request();
+        assert!(!fresh(&request, Some(&tag()), changed_at()));
+    }
+
+    #[test]
+    fn tags_come_before_dates() {
+        let request = TestRequest::default()
+            .insert_header((IF_NONE_MATCH, "\"other\""))
+            .insert_header((IF_MODIFIED_SINCE, CHANGED))
+            .to_http_request();
+        assert!(!fresh(&request, Some(&tag()), changed_at()));
+        assert!(!fresh(&request, None, changed_at()));
+    }
+
+    #[test]
+    fn modified_since() {
+        let request = TestRequest::default().insert_header((IF_MODIFIED_SINCE, CHANGED)).to_http_request();
+        assert!(fresh(&request, Some(&tag()), changed_at()));
+
+        let request = TestRequest::default().insert_header((IF_MODIFIED_SINCE, "Sun, 06 Oct 2024 11:59:59 GMT")).to_http_request();
+        assert!(!fresh(&request, None, changed_at()));
+
+        assert!(!fresh(&TestRequest::default().to_http_request(), Some(&tag()), changed_at()));
+    }
+}
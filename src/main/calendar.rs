@@ -0,0 +1,153 @@
+use actix_web::{get, HttpResponse, web};
+use actix_web::http::header::CONTENT_DISPOSITION;
+use chrono::{Days, Utc};
+
+use courselib::Course;
+
+use super::course::by_code;
+use super::error::PageError;
+use super::locale::Locale;
+
+// In octets, longer lines are folded onto the following lines, which start with a space.
+const LINE_LENGTH: usize = 75;
+
+fn escape(text: &str) -> String {
+    text
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 2);
+    let mut length = 0;
+
+    for c in line.chars() {
+        if length + c.len_utf8() > LINE_LENGTH {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+
+    folded
+}
+
+// A calendar with the study period of a course as a single all day event, if its dates are known.
+fn calendar(course: &Course, locale: Locale) -> Option<String> {
+    let start = course.start_date?;
+    // The end of all day events is exclusive.
+    let end = course.end_date? + Days::new(1);
+    let name = locale.pick_name(course.name_se.clone(), course.name_en.clone());
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_owned(),
+        "VERSION:2.0".to_owned(),
+        "PRODID:-//course_index//course_index//EN".to_owned(),
+        "BEGIN:VEVENT".to_owned(),
+        format!("UID:{}-{}@course_index", course.code, start.format("%Y%m%d")),
+        format!("DTSTAMP:{}", Utc::now().format("%Y%m%dT%H%M%SZ")),
+        format!("DTSTART;VALUE=DATE:{}", start.format("%Y%m%d")),
+        format!("DTEND;VALUE=DATE:{}", end.format("%Y%m%d")),
+        format!("SUMMARY:{}", escape(&format!("{} {}", course.code, name))),
+    ];
+    if let Some(site) = course.site {
+        lines.push(format!("LOCATION:{}", escape(&locale.label(&site))));
+    }
+    lines.extend([
+        format!("URL:{}", course.url),
+        format!("DESCRIPTION:{}", escape(&course.url)),
+        "END:VEVENT".to_owned(),
+        "END:VCALENDAR".to_owned(),
+    ]);
+
+    Some(lines.iter().map(|l| fold(l)).collect())
+}
+
+#[get("/course/{code}.ics")]
+async fn export(code: web::Path<String>, locale: Locale) -> Result<HttpResponse, PageError> {
+    let course = by_code(&code)
+        .await
+        .map_err(|e| PageError::internal(locale, e))?
+        .ok_or_else(|| PageError::unknown_course(locale, &code))?;
+    let calendar = calendar(&course, locale).ok_or_else(|| PageError::unknown_page(locale))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .insert_header((CONTENT_DISPOSITION, format!("attachment; filename=\"{}.ics\"", course.code)))
+        .body(calendar))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use courselib::{Categories, Category, CreditUnit, Difficulty, Site};
+
+    use super::*;
+
+    fn course() -> Course {
+        Course {
+            code: "D0015E".to_owned(),
+            name_se: "Objektorienterad programmering".to_owned(),
+            name_en: Some("Object oriented programming; Java, C#".to_owned()),
+            url: "https://www.ltu.se/edu/course/D00/D0015E".to_owned(),
+            points: 7.5,
+            credit_unit: CreditUnit::HigherEducation,
+            pace: None,
+            prerequisites: None,
+            register_info: None,
+            modules: None,
+            period_start: Some(2),
+            period_end: Some(2),
+            start_date: NaiveDate::from_ymd_opt(2024, 11, 4),
+            end_date: NaiveDate::from_ymd_opt(2024, 12, 31),
+            site: Some(Site::Lulea),
+            language: None,
+            difficulty: Difficulty::Undergraduate,
+            categories: Categories::from(vec![Category::Data]),
+            conduct: None,
+        }
+    }
+
+    #[test]
+    fn folding() {
+        assert_eq!(fold("SUMMARY:short"), "SUMMARY:short\r\n");
+
+        let line = format!("SUMMARY:{}", "å".repeat(80));
+        let folded = fold(&line);
+        let lines = folded.strip_suffix("\r\n").unwrap().split("\r\n").collect::<Vec<_>>();
+
+        assert!(lines.iter().all(|l| l.len() <= LINE_LENGTH), "{:?}", lines);
+        // The name and 33 two octet characters fit on the first line, 37 after the space on the next, and the last 10 on the third.
+        assert_eq!(lines.iter().map(|l| l.len()).collect::<Vec<_>>(), [74, 75, 21]);
+        assert_eq!(folded.replace("\r\n ", ""), format!("{}\r\n", line));
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape(r"a;b,c\d"), r"a\;b\,c\\d");
+        assert_eq!(escape("first\r\nsecond\nthird"), r"first\nsecond\nthird");
+    }
+
+    #[test]
+    fn exclusive_end() {
+        let calendar = calendar(&course(), Locale::English).unwrap();
+
+        assert!(calendar.contains("DTSTART;VALUE=DATE:20241104\r\n"));
+        // The day after the last one, which here is in the next year.
+        assert!(calendar.contains("DTEND;VALUE=DATE:20250101\r\n"));
+        assert!(calendar.contains(r"SUMMARY:D0015E Object oriented programming\; Java\, C#"));
+        assert!(calendar.lines().all(|l| l.len() <= LINE_LENGTH));
+    }
+
+    #[test]
+    fn unknown_dates() {
+        let course = Course { end_date: None, ..course() };
+        assert_eq!(calendar(&course, Locale::English), None);
+    }
+}
@@ -0,0 +1,118 @@
+use actix_web::{get, HttpRequest, HttpResponse};
+use askama::Template;
+use itertools::Itertools;
+use serde::Deserialize;
+use sqlx::{Error as SqlxError, query_as};
+
+use courselib::Course;
+
+use super::CONNECTION;
+use super::course::{modules, periods};
+use super::error::{PageError, parse_query};
+use super::locale::Locale;
+use super::search::comma_separated;
+
+const MIN_COURSES: usize = 2;
+const MAX_COURSES: usize = 5;
+
+#[derive(Deserialize, Debug, Clone)]
+struct CompareQuery {
+    #[serde(default, with = "comma_separated")]
+    codes: Vec<String>,
+}
+
+struct Row {
+    label: &'static str,
+    values: Vec<String>,
+    differs: bool,
+}
+
+impl Row {
+    fn new(label: &'static str, values: Vec<Option<String>>) -> Self {
+        let values = values
+            .into_iter()
+            .map(|v| v.unwrap_or_else(|| "–".to_owned()))
+            .collect::<Vec<_>>();
+
+        Self {
+            label,
+            differs: !values.iter().all_equal(),
+            values,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "../html/compare.html")]
+struct CompareTemplate {
+    locale: Locale,
+    switch_language: String,
+    // Codes and names.
+    courses: Vec<(String, String)>,
+    rows: Vec<Row>,
+}
+
+impl CompareTemplate {
+    fn new(courses: Vec<Course>, locale: Locale, request: &HttpRequest) -> Self {
+        let row = |label, value: &dyn Fn(&Course) -> Option<String>| Row::new(label, courses.iter().map(value).collect());
+        let t = |swedish, english| locale.t(swedish, english);
+
+        let rows = vec![
+            row(t("Poäng", "Credits"), &|c| Some(format!("{} {}", locale.number(c.points), locale.label(&c.credit_unit)))),
+            row(t("Studieform", "Pace"), &|c| c.pace.map(|p| locale.label(&p))),
+            row(t("Period", "Study period"), &|c| periods(c, locale)),
+            row(t("Studieort", "Location"), &|c| c.site.map(|s| locale.label(&s))),
+            row(t("Språk", "Language"), &|c| c.language.map(|l| locale.label(&l))),
+            row(t("Typ", "Level"), &|c| Some(locale.label(&c.difficulty))),
+            row(t("Kategorier", "Subjects"), &|c| Some(c.categories.iter().map(|c| locale.label(c)).join(", ")).filter(|c| !c.is_empty())),
+            row(t("Förkunskapskrav", "Entry requirements"), &|c| c.prerequisites.clone()),
+            row(t("Moduler", "Modules"), &|c| c.modules.clone().map(modules)),
+        ];
+
+        Self {
+            locale,
+            switch_language: locale.switch_link(request),
+            courses: courses.iter().map(|c| (c.code.clone(), locale.name(c).to_owned())).collect(),
+            rows,
+        }
+    }
+}
+
+pub(crate) async fn by_codes(codes: &[String]) -> Result<Vec<Course>, SqlxError> {
+    query_as!(
+        Course,
+        r#"SELECT code, name_se, name_en, url, points, credit_unit as "credit_unit: _", pace as "pace: _", prerequisites, register_info, modules, period_start, period_end, start_date, end_date, site as "site: _", language as "language: _", difficulty as "difficulty: _", categories as "categories: _", conduct
+        FROM courses
+        WHERE code = ANY($1)"#,
+        codes
+    )
+        .fetch_all(CONNECTION.get().unwrap())
+        .await
+}
+
+// Takes the codes like `/compare?codes=D0015E,D0010E`, and shows the courses in that order.
+#[get("/compare")]
+async fn page(locale: Locale, request: HttpRequest) -> Result<HttpResponse, PageError> {
+    let query = parse_query::<CompareQuery>(locale, request.query_string())?;
+    let codes = query.codes.iter().map(|c| c.trim().to_uppercase()).unique().collect::<Vec<_>>();
+
+    if !(MIN_COURSES..=MAX_COURSES).contains(&codes.len()) {
+        return Err(PageError::invalid_parameter(
+            locale,
+            "codes",
+            format_args!("between {} and {} different courses can be compared", MIN_COURSES, MAX_COURSES)
+        ))
+    }
+
+    let mut found = by_codes(&codes).await.map_err(|e| PageError::internal(locale, e))?;
+    let mut courses = Vec::with_capacity(codes.len());
+    for code in &codes {
+        match found.iter().position(|c| &c.code == code) {
+            Some(i) => courses.push(found.swap_remove(i)),
+            None => return Err(PageError::unknown_course(locale, code)),
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .body(CompareTemplate::new(courses, locale, &request).render().unwrap()))
+}
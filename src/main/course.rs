@@ -1,94 +1,259 @@
-use actix_web::{get, HttpResponse, Responder, web};
+use actix_web::{get, HttpRequest, HttpResponse, web};
+use actix_web::http::header::LOCATION;
 use askama::filters::capitalize;
 use askama::Template;
 use serde::Deserialize;
-use sqlx::query_as;
+use sqlx::{Error as SqlxError, query_as, query_scalar};
 
-use courselib::{Course, Pace};
+use courselib::{Course, course_code_ranges};
 
 use super::CONNECTION;
+use super::autocomplete::find;
+use super::cache::{changed, get, insert, Key, not_modified, Page};
+use super::error::{PageError, parse_query};
+use super::format::{course_markdown, csv, Format};
+use super::locale::Locale;
+use super::metadata::{description, json_ld, script};
+
+const RELATED_COURSES: usize = 5;
+// Where the site is served from, like `https://kurser.example.se`, set up next to the connection string.
+// Links in pages aren't built from the `Host` of requests, since pages are cached for everyone.
+const BASE_URL: &str = include_str!("../../base_url");
 
 #[derive(Deserialize, Debug, Clone)]
 struct CourseQuery {
     id: i32,
 }
 
+// Texts are split around the course codes they mention, so that the codes can be linked.
+enum Segment {
+    Text(String),
+    // Code and name.
+    Course(String, String),
+    UnknownCourse(String),
+}
+
+fn link_codes(text: &str, locale: Locale) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut end = 0;
+
+    for range in course_code_ranges(text) {
+        if range.start > end {
+            segments.push(Segment::Text(text[end..range.start].to_owned()));
+        }
+        let code = &text[range.clone()];
+        segments.push(match find(code) {
+            Some(course) => Segment::Course(course.code, locale.pick_name(course.name_se, course.name_en)),
+            None => Segment::UnknownCourse(code.to_owned()),
+        });
+        end = range.end;
+    }
+    if end < text.len() {
+        segments.push(Segment::Text(text[end..].to_owned()));
+    }
+
+    segments
+}
+
+pub(crate) fn periods(course: &Course, locale: Locale) -> Option<String> {
+    match (course.period_start, course.period_end) {
+        (Some(start), Some(end)) if start != end =>
+            Some(format!("{} {} {}", start, locale.t("till", "to"), end)),
+        (Some(start), _) | (None, Some(start)) =>
+            Some(start.to_string()),
+        (None, None) =>
+            None
+    }
+}
+
+pub(crate) fn modules(modules: String) -> String {
+    capitalize(modules).unwrap()
+}
+
 #[derive(Template)]
 #[template(path = "../html/course.html")]
 struct CourseTemplate {
+    locale: Locale,
+    switch_language: String,
+    // The address of this page, for link previews.
+    page_url: String,
+    description: String,
+    json_ld: String,
     title: String,
     code: String,
-    name_se: String,
-    name_en: Option<String>,
+    name: String,
+    // The name in the other language, when it differs.
+    other_name: Option<String>,
     url: String,
     points: String,
-    pace: Option<Pace>,
-    prerequisites: Option<String>,
-    register_info: Option<String>,
+    credit_unit: String,
+    pace: Option<String>,
+    prerequisites: Option<Vec<Segment>>,
+    register_info: Option<Vec<Segment>>,
     modules: Option<String>,
     periods: Option<String>,
     site: Option<String>,
     language: Option<String>,
     difficulty: String,
     categories: Vec<String>,
-    conduct: Option<String>,
+    conduct: Option<Vec<Segment>>,
+    // Codes and names.
+    related: Vec<(String, String)>,
+    calendar: Option<String>,
 }
 
-impl From<Course> for CourseTemplate {
-    fn from(value: Course) -> Self {
+impl CourseTemplate {
+    fn new(value: Course, related: Vec<Course>, locale: Locale, request: &HttpRequest) -> Self {
+        let name = locale.name(&value).to_owned();
+        let other_name = match locale {
+            Locale::Swedish => value.name_en.clone(),
+            Locale::English => Some(value.name_se.clone()),
+        };
+
+        let periods = periods(&value, locale);
+
         Self {
-            title: format!("{} {}", value.code, value.name_se),
+            locale,
+            switch_language: locale.switch_link(request),
+            page_url: format!("{}/course/{}", BASE_URL.trim_end().trim_end_matches('/'), value.code),
+            description: description(&value, locale),
+            json_ld: script(&json_ld(&value, locale)),
+            title: format!("{} {}", value.code, name),
+            calendar: value.start_date.and(value.end_date).map(|_| format!("/course/{}.ics", value.code)),
             code: value.code,
-            name_en: value.name_en.filter(|n| n != &value.name_se),
-            name_se: value.name_se,
+            other_name: other_name.filter(|n| n != &name),
+            name,
             url: value.url,
-            points: value.points.to_string().replace('.', ","),
-            pace: value.pace,
-            prerequisites: value.prerequisites,
-            register_info: value.register_info,
-            modules: value.modules.map(|m| capitalize(m).unwrap()),
-            periods: match (value.period_start, value.period_end) {
-                (Some(start), Some(end)) if start != end =>
-                    Some(format!("{} till {}", start, end)),
-                (Some(start), _) | (None, Some(start)) =>
-                    Some(start.to_string()),
-                (None, None) =>
-                    None
-            },
-            site: value.site.map(|s| s.to_string()),
-            language: value.language.map(|l| l.to_string()),
-            difficulty: value.difficulty.to_string(),
-            categories: value.categories.iter().map(|c| c.to_string()).collect(),
-            conduct: value.conduct,
+            points: locale.number(value.points),
+            credit_unit: locale.label(&value.credit_unit),
+            pace: value.pace.map(|p| locale.label(&p)),
+            prerequisites: value.prerequisites.map(|p| link_codes(&p, locale)),
+            register_info: value.register_info.map(|r| link_codes(&r, locale)),
+            periods,
+            modules: value.modules.map(modules),
+            site: value.site.map(|s| locale.label(&s)),
+            language: value.language.map(|l| locale.label(&l)),
+            difficulty: locale.label(&value.difficulty),
+            categories: value.categories.iter().map(|c| locale.label(c)).collect(),
+            conduct: value.conduct.map(|c| link_codes(&c, locale)),
+            related: related.iter().map(|c| (c.code.clone(), locale.name(c).to_owned())).collect(),
         }
     }
 }
 
-#[get("/course")]
-async fn page(query: web::Query<CourseQuery>) -> impl Responder {
-    let res = query_as!(
+pub(crate) async fn by_code(code: &str) -> Result<Option<Course>, SqlxError> {
+    query_as!(
         Course,
-        r#"SELECT code, name_se, name_en, url, points, pace as "pace: _", prerequisites, register_info, modules, period_start, period_end, site as "site: _", language as "language: _", difficulty as "difficulty: _", categories as "categories: _", conduct
+        r#"SELECT code, name_se, name_en, url, points, credit_unit as "credit_unit: _", pace as "pace: _", prerequisites, register_info, modules, period_start, period_end, start_date, end_date, site as "site: _", language as "language: _", difficulty as "difficulty: _", categories as "categories: _", conduct
         FROM courses
-        WHERE id = $1"#,
-        query.id
+        WHERE code = $1"#,
+        code.to_uppercase()
     )
         .fetch_optional(CONNECTION.get().unwrap())
-        .await;
+        .await
+}
 
-    match res {
-        Ok(Some(course)) => {
-            HttpResponse::Ok()
-                .body(Into::<CourseTemplate>::into(course).render().unwrap())
-        }
-        Ok(None) => {
-            // that id doesn't exist
-            HttpResponse::NotFound().finish() // FIXME
-        }
-        Err(e) => {
-            eprintln!("{}", e);
-            // sql error
-            HttpResponse::InternalServerError().finish() // FIXME
+// Only courses with anything in common with `course` are fetched to be ranked, rather than the whole table.
+pub(crate) async fn related(course: &Course) -> Result<Vec<Course>, SqlxError> {
+    let categories = course.categories.iter().map(|c| format!("{:?}", c)).collect::<Vec<_>>();
+    let prerequisites = course.prerequisite_codes();
+    let codes = prerequisites.iter().map(|&c| c.to_owned()).collect::<Vec<_>>();
+    let code_patterns = prerequisites.iter().map(|c| format!("%{}%", c)).collect::<Vec<_>>();
+    let word_patterns = course.name_words().iter().map(|w| format!("%{}%", w)).collect::<Vec<_>>();
+
+    let candidates = query_as!(
+        Course,
+        r#"SELECT code, name_se, name_en, url, points, credit_unit as "credit_unit: _", pace as "pace: _", prerequisites, register_info, modules, period_start, period_end, start_date, end_date, site as "site: _", language as "language: _", difficulty as "difficulty: _", categories as "categories: _", conduct
+        FROM courses
+        WHERE code <> $1 AND (
+            categories && $2::TEXT[]::category_enum[]
+            OR code = ANY($3)
+            OR prerequisites LIKE '%' || $1 || '%'
+            OR prerequisites LIKE ANY($4)
+            OR name_se ILIKE ANY($5)
+            OR name_en ILIKE ANY($5)
+        )"#,
+        course.code,
+        &categories,
+        &codes,
+        &code_patterns,
+        &word_patterns
+    )
+        .fetch_all(CONNECTION.get().unwrap())
+        .await?;
+
+    Ok(course
+        .related(&candidates, RELATED_COURSES)
+        .into_iter()
+        .cloned()
+        .collect())
+}
+
+// Redirects keep the query string, so that the language and format chosen with it stay the same.
+fn with_query(path: String, query_string: &str) -> String {
+    if query_string.is_empty() {
+        path
+    } else {
+        format!("{}?{}", path, query_string)
+    }
+}
+
+// Ids change whenever the table is rebuilt, so they only remain for old links.
+#[get("/course")]
+async fn redirect(locale: Locale, request: HttpRequest) -> Result<HttpResponse, PageError> {
+    let query = parse_query::<CourseQuery>(locale, request.query_string())?;
+    let code = query_scalar!("SELECT code FROM courses WHERE id = $1", query.id)
+        .fetch_optional(CONNECTION.get().unwrap())
+        .await
+        .map_err(|e| PageError::internal(locale, e))?
+        .ok_or_else(|| PageError::unknown_page(locale))?;
+
+    let rest = serde_urlencoded::from_str::<Vec<(String, String)>>(request.query_string())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(key, _)| key != "id")
+        .collect::<Vec<_>>();
+
+    Ok(HttpResponse::MovedPermanently()
+        .insert_header((LOCATION, with_query(format!("/course/{}", code), &serde_urlencoded::to_string(rest).unwrap())))
+        .finish())
+}
+
+#[get("/course/{code}")]
+async fn page(code: web::Path<String>, locale: Locale, format: Format, request: HttpRequest) -> Result<HttpResponse, PageError> {
+    let key = Key::of(&request, locale, format);
+    if let Some(page) = key.as_ref().and_then(get) {
+        return Ok(page.respond(&request))
+    }
+
+    let changed = changed();
+    let course = by_code(&code)
+        .await
+        .map_err(|e| PageError::internal(locale, e))?
+        .ok_or_else(|| PageError::unknown_course(locale, &code))?;
+
+    if course.code != *code {
+        return Ok(HttpResponse::MovedPermanently()
+            .insert_header((LOCATION, with_query(format!("/course/{}", course.code), request.query_string())))
+            .finish())
+    }
+    if let Some(response) = not_modified(&request) {
+        return Ok(response)
+    }
+
+    let body = match format {
+        Format::Html => {
+            let related = related(&course).await.map_err(|e| PageError::internal(locale, e))?;
+            CourseTemplate::new(course, related, locale, &request).render().unwrap()
         }
+        Format::Json => serde_json::to_string(&course).unwrap(),
+        Format::Markdown => course_markdown(&course, locale),
+        Format::Csv => csv(&[course]),
+    };
+
+    let page = Page::new(format, body, changed);
+    if let Some(key) = key {
+        insert(key, page.clone());
     }
+    Ok(page.respond(&request))
 }
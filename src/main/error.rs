@@ -0,0 +1,158 @@
+use std::fmt::{Display, Formatter};
+
+use actix_web::{HttpResponse, ResponseError};
+use actix_web::http::StatusCode;
+use askama::Template;
+use serde::de::DeserializeOwned;
+
+use super::autocomplete::{suggestions, Suggestion};
+use super::locale::Locale;
+use super::search::QueryError;
+
+const SUGGESTIONS: usize = 5;
+
+#[derive(Debug, Clone)]
+enum Kind {
+    UnknownPage,
+    UnknownCourse(String),
+    UnknownSavedSearch(String),
+    // The parameters which could be at fault, if they are known, and why.
+    InvalidQuery(Vec<String>, String),
+    Internal,
+}
+
+// Errors of the web pages, which are shown as pages in the same language.
+#[derive(Debug, Clone)]
+pub(crate) struct PageError {
+    locale: Locale,
+    kind: Kind,
+}
+
+impl PageError {
+    pub(crate) fn unknown_page(locale: Locale) -> Self {
+        Self { locale, kind: Kind::UnknownPage }
+    }
+
+    pub(crate) fn unknown_course(locale: Locale, code: &str) -> Self {
+        Self { locale, kind: Kind::UnknownCourse(code.to_owned()) }
+    }
+
+    pub(crate) fn unknown_saved_search(locale: Locale, slug: &str) -> Self {
+        Self { locale, kind: Kind::UnknownSavedSearch(slug.to_owned()) }
+    }
+
+    // `text` is whether the search was written in the query language.
+    pub(crate) fn invalid_query(locale: Locale, error: QueryError, text: bool) -> Self {
+        let parameters = error.parameters(text).iter().map(|&p| p.to_owned()).collect();
+        Self { locale, kind: Kind::InvalidQuery(parameters, error.to_string()) }
+    }
+
+    pub(crate) fn invalid_parameter(locale: Locale, parameter: &str, reason: impl Display) -> Self {
+        Self { locale, kind: Kind::InvalidQuery(vec![parameter.to_owned()], reason.to_string()) }
+    }
+
+    // The cause is only logged, not shown.
+    pub(crate) fn internal(locale: Locale, error: impl Display) -> Self {
+        eprintln!("{}", error);
+        Self { locale, kind: Kind::Internal }
+    }
+}
+
+impl Display for PageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            Kind::UnknownPage => write!(f, "page not found"),
+            Kind::UnknownCourse(code) => write!(f, "no course with the code {}", code),
+            Kind::UnknownSavedSearch(slug) => write!(f, "no saved search {}", slug),
+            Kind::InvalidQuery(parameters, reason) if parameters.is_empty() => write!(f, "{}", reason),
+            Kind::InvalidQuery(parameters, reason) => write!(f, "invalid {}: {}", parameters.join(", "), reason),
+            Kind::Internal => write!(f, "internal server error"),
+        }
+    }
+}
+
+impl ResponseError for PageError {
+    fn status_code(&self) -> StatusCode {
+        match self.kind {
+            Kind::UnknownPage | Kind::UnknownCourse(_) | Kind::UnknownSavedSearch(_) => StatusCode::NOT_FOUND,
+            Kind::InvalidQuery(..) => StatusCode::BAD_REQUEST,
+            Kind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .body(ErrorTemplate::from(self).render().unwrap())
+    }
+}
+
+#[derive(Template)]
+#[template(path = "../html/error.html")]
+struct ErrorTemplate {
+    locale: Locale,
+    heading: String,
+    detail: Option<String>,
+    // Codes and names of courses the visitor might have meant.
+    suggestions: Vec<(String, String)>,
+}
+
+// Courses with codes or names like an unknown code, found by trying shorter and shorter prefixes of it.
+fn similar(code: &str) -> Vec<Suggestion> {
+    code
+        .char_indices()
+        .rev()
+        .map(|(i, c)| suggestions(&code[..i + c.len_utf8()], SUGGESTIONS))
+        .find(|s| !s.is_empty())
+        .unwrap_or_default()
+}
+
+impl From<&PageError> for ErrorTemplate {
+    fn from(value: &PageError) -> Self {
+        let locale = value.locale;
+        let (heading, detail) = match &value.kind {
+            Kind::UnknownPage =>
+                (locale.t("Sidan finns inte", "Page not found").to_owned(), None),
+            Kind::UnknownCourse(code) =>
+                (format!("{} {}", locale.t("Det finns ingen kurs med koden", "There is no course with the code"), code), None),
+            Kind::UnknownSavedSearch(_) =>
+                (locale.t("Den sparade sökningen finns inte", "The saved search doesn't exist").to_owned(), None),
+            Kind::InvalidQuery(parameters, reason) if parameters.is_empty() =>
+                (locale.t("Ogiltig sökning", "Invalid search").to_owned(), Some(reason.clone())),
+            Kind::InvalidQuery(parameters, reason) =>
+                (locale.t("Ogiltig sökning", "Invalid search").to_owned(),
+                 Some(format!("{} {}: {}", locale.t("Fel i", "Error in"), parameters.join(", "), reason))),
+            Kind::Internal =>
+                (locale.t("Något gick fel", "Something went wrong").to_owned(),
+                 Some(locale.t("Försök igen senare.", "Please try again later.").to_owned())),
+        };
+        let suggestions = match &value.kind {
+            Kind::UnknownCourse(code) => similar(code)
+                .into_iter()
+                .map(|s| (s.code, locale.pick_name(s.name_se, s.name_en)))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Self { locale, heading, detail, suggestions }
+    }
+}
+
+// Parses the query string of a page, finding which parameter was wrong when it can't be parsed.
+pub(crate) fn parse_query<T: DeserializeOwned>(locale: Locale, query_string: &str) -> Result<T, PageError> {
+    serde_urlencoded::from_str(query_string).map_err(|e| {
+        // The error doesn't name the parameter, so each one is tried on its own.
+        let parameters = serde_urlencoded::from_str::<Vec<(String, String)>>(query_string)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|pair| serde_urlencoded::to_string([pair])
+                .is_ok_and(|p| serde_urlencoded::from_str::<T>(&p).is_err()))
+            .map(|(name, _)| name)
+            .collect();
+
+        PageError { locale, kind: Kind::InvalidQuery(parameters, e.to_string()) }
+    })
+}
+
+pub(crate) async fn not_found(locale: Locale) -> Result<HttpResponse, PageError> {
+    Err(PageError::unknown_page(locale))
+}
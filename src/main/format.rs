@@ -0,0 +1,325 @@
+use std::future::{Ready, ready};
+
+use actix_web::{FromRequest, HttpRequest, HttpResponse, HttpResponseBuilder};
+use actix_web::dev::Payload;
+use actix_web::http::header::{ACCEPT, VARY};
+use serde::{de::Error as DeError, Deserialize, Deserializer};
+use serde_json::Value;
+
+use courselib::Course;
+
+use super::course::{modules, periods};
+use super::error::{PageError, parse_query};
+use super::locale::Locale;
+use super::search::{Cursor, SearchQuery, SearchResults, with_cursor};
+
+// Pages vary with the headers that the format and the locale are chosen by.
+pub(crate) const VARY_HEADERS: &str = "Accept, Accept-Language, Cookie";
+
+// The columns of CSV files, named and valued like the fields in JSON.
+const CSV_COLUMNS: [&str; 19] = [
+    "code",
+    "name_se",
+    "name_en",
+    "url",
+    "points",
+    "credit_unit",
+    "pace",
+    "prerequisites",
+    "register_info",
+    "modules",
+    "period_start",
+    "period_end",
+    "start_date",
+    "end_date",
+    "site",
+    "language",
+    "difficulty",
+    "categories",
+    "conduct",
+];
+
+// The representation of a page, chosen with the `format` parameter or else by `Accept`, where anything unknown gets HTML.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Format {
+    #[default]
+    Html,
+    Json,
+    Markdown,
+    Csv,
+}
+
+impl Format {
+    fn code(self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Json => "json",
+            Self::Markdown => "markdown",
+            Self::Csv => "csv",
+        }
+    }
+
+    fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "html" => Some(Self::Html),
+            "json" => Some(Self::Json),
+            "markdown" => Some(Self::Markdown),
+            "csv" => Some(Self::Csv),
+            _ => None
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "text/html" | "application/xhtml+xml" => Some(Self::Html),
+            "application/json" => Some(Self::Json),
+            "text/markdown" => Some(Self::Markdown),
+            "text/csv" => Some(Self::Csv),
+            _ => None
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Html => "text/html; charset=utf-8",
+            Self::Json => "application/json",
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    // Takes the preferred representation among the supported ones, where wildcards like `*/*` match the default.
+    fn accepted(header: &str) -> Option<Self> {
+        header
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let media_type = parts.next()?.trim().to_lowercase();
+                let quality = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map(|q| q.parse::<f32>().ok())
+                    .unwrap_or(Some(1.))?;
+                let format = Self::from_media_type(&media_type)
+                    .or_else(|| media_type.ends_with("/*").then(Self::default))?;
+                Some((format, quality))
+            })
+            .filter(|&(_, quality)| quality > 0.)
+            .fold(None, |best: Option<(Self, f32)>, (format, quality)| match best {
+                Some((_, best_quality)) if best_quality >= quality => best,
+                _ => Some((format, quality)),
+            })
+            .map(|(format, _)| format)
+    }
+
+    fn of(request: &HttpRequest) -> Result<Self, PageError> {
+        let query = parse_query::<FormatQuery>(Locale::of(request), request.query_string())?;
+
+        Ok(query.format
+            .or_else(|| request
+                .headers()
+                .get(ACCEPT)
+                .and_then(|h| h.to_str().ok())
+                .and_then(Self::accepted))
+            .unwrap_or_default())
+    }
+
+    // Responses vary with `Accept` even when the parameter chose the format, since it could be left out.
+    pub(crate) fn respond(self) -> HttpResponseBuilder {
+        let mut response = HttpResponse::Ok();
+        response
+            .content_type(self.content_type())
+            .insert_header((VARY, VARY_HEADERS));
+        response
+    }
+}
+
+impl<'de> Deserialize<'de> for Format {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        let code = String::deserialize(deserializer)?;
+        Self::from_code(&code).ok_or_else(|| DeError::unknown_variant(&code, &["html", "json", "markdown", "csv"]))
+    }
+}
+
+impl FromRequest for Format {
+    type Error = PageError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Self::of(request))
+    }
+}
+
+#[derive(Deserialize)]
+struct FormatQuery {
+    #[serde(default)]
+    format: Option<Format>,
+}
+
+// Backslashes the characters which would otherwise format the text, and keeps it on one line for tables.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\r' => {}
+            '\n' => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub(crate) fn course_markdown(course: &Course, locale: Locale) -> String {
+    let t = |swedish, english| locale.t(swedish, english);
+    let mut lines = vec![format!("# {} {}", course.code, escape(locale.name(course))), String::new()];
+
+    let other_name = match locale {
+        Locale::Swedish => course.name_en.as_deref(),
+        Locale::English => Some(course.name_se.as_str()),
+    };
+    if let Some(other_name) = other_name.filter(|&n| n != locale.name(course)) {
+        lines.extend([format!("*{}*", escape(other_name)), String::new()]);
+    }
+
+    let dates = course.start_date.zip(course.end_date).map(|(start, end)| format!("{} – {}", start, end));
+    let fields = [
+        (t("Poäng", "Credits"), Some(format!("{} {}", locale.number(course.points), locale.label(&course.credit_unit)))),
+        (t("Studieform", "Pace"), course.pace.map(|p| locale.label(&p))),
+        (t("Period", "Study period"), periods(course, locale)),
+        (t("Datum", "Dates"), dates),
+        (t("Studieort", "Location"), course.site.map(|s| locale.label(&s))),
+        (t("Språk", "Language"), course.language.map(|l| locale.label(&l))),
+        (t("Typ", "Level"), Some(locale.label(&course.difficulty))),
+        (t("Kategorier", "Subjects"), Some(course.categories.iter().map(|c| locale.label(c)).collect::<Vec<_>>().join(", ")).filter(|c| !c.is_empty())),
+    ];
+    lines.extend(fields
+        .into_iter()
+        .filter_map(|(label, value)| Some(format!("- **{}:** {}", label, escape(&value?)))));
+
+    let sections = [
+        (t("Förkunskapskrav", "Entry requirements"), course.prerequisites.clone()),
+        (t("Sökinformation", "Application information"), course.register_info.clone()),
+        (t("Moduler", "Modules"), course.modules.clone().map(modules)),
+        (t("Genomförande", "Conduct"), course.conduct.clone()),
+    ];
+    for (heading, text) in sections {
+        if let Some(text) = text {
+            lines.extend([String::new(), format!("## {}", heading), String::new(), escape(&text)]);
+        }
+    }
+
+    lines.extend([String::new(), format!("[{}]({})", t("Visa på LTU:s hemsida", "Show on the LTU website"), course.url), String::new()]);
+    lines.join("\n")
+}
+
+// The results as a table, with links to the other pages in Markdown too.
+pub(crate) fn search_markdown(results: &SearchResults, query: &SearchQuery, locale: Locale) -> String {
+    let t = |swedish, english| locale.t(swedish, english);
+    let mut lines = vec![
+        format!("# {}", t("Kurser", "Courses")),
+        String::new(),
+        format!("{} {}", results.total, t("träffar", "results")),
+        String::new(),
+        format!("| {} | {} | {} | {} | {} | {} |",
+            t("Kod", "Code"), t("Namn", "Name"), t("Poäng", "Credits"), t("Studieform", "Pace"), t("Period", "Study period"), t("Studieort", "Location")),
+        "| --- | --- | --- | --- | --- | --- |".to_owned(),
+    ];
+
+    lines.extend(results.courses.iter().map(|c| format!(
+        "| [{}](/course/{}) | {} | {} | {} | {} | {} |",
+        c.code,
+        c.code,
+        escape(locale.name(c)),
+        escape(&format!("{} {}", locale.number(c.points), locale.label(&c.credit_unit))),
+        escape(&c.pace.map(|p| locale.label(&p)).unwrap_or_default()),
+        escape(&periods(c, locale).unwrap_or_default()),
+        escape(&c.site.map(|s| locale.label(&s)).unwrap_or_default()),
+    )));
+
+    let link = |label, cursor: &Option<Cursor>| cursor
+        .clone()
+        .map(|c| format!("[{}]({}&format={})", label, with_cursor(query, c), Format::Markdown.code()));
+    let pages = [link(t("Föregående", "Previous"), &results.previous), link(t("Nästa", "Next"), &results.next)]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    if !pages.is_empty() {
+        lines.extend([String::new(), pages.join(" ")]);
+    }
+
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+// Quotes fields containing separators, quotes or line breaks, as in RFC 4180.
+fn csv_field(value: &Value) -> String {
+    let text = match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(values) => values.iter().map(csv_field).collect::<Vec<_>>().join(","),
+        value => value.to_string(),
+    };
+
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+// A header row followed by one row per course.
+pub(crate) fn csv(courses: &[Course]) -> String {
+    let mut rows = vec![CSV_COLUMNS.join(",")];
+
+    rows.extend(courses.iter().map(|course| {
+        let value = serde_json::to_value(course).unwrap();
+        CSV_COLUMNS
+            .iter()
+            .map(|&column| csv_field(&value[column]))
+            .collect::<Vec<_>>()
+            .join(",")
+    }));
+
+    rows.into_iter().map(|r| r + "\r\n").collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn browsers_get_html() {
+        assert_eq!(
+            Format::accepted("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"),
+            Some(Format::Html)
+        );
+        assert_eq!(Format::accepted("*/*"), Some(Format::Html));
+    }
+
+    #[test]
+    fn preferred_format() {
+        assert_eq!(Format::accepted("text/markdown"), Some(Format::Markdown));
+        assert_eq!(Format::accepted("text/html;q=0.5, text/csv"), Some(Format::Csv));
+        assert_eq!(Format::accepted("Application/JSON, text/html"), Some(Format::Json));
+        assert_eq!(Format::accepted("application/json;q=0, text/markdown;q=0.1"), Some(Format::Markdown));
+        assert_eq!(Format::accepted("image/png"), None);
+    }
+
+    #[test]
+    fn csv_quoting() {
+        assert_eq!(csv_field(&Value::Null), "");
+        assert_eq!(csv_field(&Value::from(7.5)), "7.5");
+        assert_eq!(csv_field(&Value::from("Luleå")), "Luleå");
+        assert_eq!(csv_field(&Value::from("D0015E, \"OOP\"")), "\"D0015E, \"\"OOP\"\"\"");
+        assert_eq!(csv_field(&Value::from(vec!["dat", "tek"])), "\"dat,tek\"");
+        assert_eq!(csv_field(&Value::from("a\nb")), "\"a\nb\"");
+    }
+
+    #[test]
+    fn markdown_escaping() {
+        assert_eq!(escape("C# | *fast*\nprogramming"), "C\\# \\| \\*fast\\* programming");
+    }
+}
@@ -0,0 +1,395 @@
+use actix_web::{post, HttpResponse, Responder, web};
+use async_graphql::{EmptyMutation, EmptySubscription, Enum, Error, InputObject, MergedObject, Object, Request, Result, Schema, SimpleObject};
+use lazy_static::lazy_static;
+
+use courselib::Percentage;
+
+use super::course::{by_code, related};
+use super::search::{Cursor, page_size, prepare, search as run_search, SearchQuery, TextQuery};
+
+// Deep enough for the introspection query that tools send, which nests far more than any query on courses.
+const MAX_DEPTH: usize = 16;
+// Every field counts as 1 and the fields of a page count once per course, so this is about a full page with every field.
+const MAX_COMPLEXITY: usize = 2500;
+// Related courses are ranked among many candidates, which costs about as much as a page of courses.
+const RELATED_COMPLEXITY: usize = 250;
+
+// The GraphQL enums mirror the ones used elsewhere, which can't derive `Enum` themselves.
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "courselib::CreditUnit")]
+enum CreditUnit {
+    HigherEducation,
+    Preparatory,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "courselib::Time")]
+enum Time {
+    Day,
+    Weekend,
+    Mixed,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "courselib::Site")]
+enum Site {
+    Stockholm,
+    Pitea,
+    Skelleftea,
+    Lulea,
+    Kiruna,
+    LocationIndependent,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "courselib::Language")]
+enum Language {
+    Swedish,
+    English,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "courselib::Difficulty")]
+enum Difficulty {
+    Preparatory,
+    Undergraduate,
+    Advanced,
+    ContinuationUndergraduate,
+    ContinuationAdvanced,
+    IntroductoryUndergraduate,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "courselib::Category")]
+enum Category {
+    Data,
+    Economy,
+    Environment,
+    Health,
+    Law,
+    Education,
+    Music,
+    Social,
+    Technology,
+    Media,
+    Interdisciplinary,
+    Language,
+    Mathematics,
+    Information,
+    Humanities,
+    Behavioral,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "super::search::SortOrder")]
+enum SortOrder {
+    Code,
+    Name,
+    Points,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "super::search::Term")]
+enum Term {
+    Autumn,
+    Spring,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "super::search::PeriodSelectMode")]
+enum PeriodSelectMode {
+    Only,
+    Starts,
+    Ends,
+    Spans,
+    Overlaps,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "super::search::CategorySelectMode")]
+enum CategorySelectMode {
+    Any,
+    All,
+    None,
+}
+
+#[derive(SimpleObject)]
+struct Pace {
+    time: Time,
+    percentage: i32,
+}
+
+impl From<courselib::Pace> for Pace {
+    fn from(value: courselib::Pace) -> Self {
+        Self {
+            time: value.time().into(),
+            percentage: *value.percentage() as i32,
+        }
+    }
+}
+
+#[derive(InputObject)]
+#[graphql(name = "PaceInput")]
+struct PaceInput {
+    time: Time,
+    percentage: i32,
+}
+
+impl TryFrom<PaceInput> for courselib::Pace {
+    type Error = Error;
+
+    fn try_from(value: PaceInput) -> Result<Self> {
+        let percentage = i8::try_from(value.percentage)
+            .ok()
+            .and_then(|p| Percentage::try_from(p).ok())
+            .ok_or_else(|| Error::new("pace percentages must be between 0 and 100"))?;
+
+        Ok(Self::new(value.time.into(), percentage))
+    }
+}
+
+struct Course(courselib::Course);
+
+#[Object]
+impl Course {
+    async fn code(&self) -> &str {
+        &self.0.code
+    }
+
+    async fn name_se(&self) -> &str {
+        &self.0.name_se
+    }
+
+    async fn name_en(&self) -> Option<&str> {
+        self.0.name_en.as_deref()
+    }
+
+    async fn url(&self) -> &str {
+        &self.0.url
+    }
+
+    async fn points(&self) -> f32 {
+        self.0.points
+    }
+
+    async fn credit_unit(&self) -> CreditUnit {
+        self.0.credit_unit.into()
+    }
+
+    async fn pace(&self) -> Option<Pace> {
+        self.0.pace.map(Pace::from)
+    }
+
+    async fn prerequisites(&self) -> Option<&str> {
+        self.0.prerequisites.as_deref()
+    }
+
+    async fn register_info(&self) -> Option<&str> {
+        self.0.register_info.as_deref()
+    }
+
+    async fn modules(&self) -> Option<&str> {
+        self.0.modules.as_deref()
+    }
+
+    async fn period_start(&self) -> Option<i16> {
+        self.0.period_start
+    }
+
+    async fn period_end(&self) -> Option<i16> {
+        self.0.period_end
+    }
+
+    async fn site(&self) -> Option<Site> {
+        self.0.site.map(Site::from)
+    }
+
+    async fn language(&self) -> Option<Language> {
+        self.0.language.map(Language::from)
+    }
+
+    async fn difficulty(&self) -> Difficulty {
+        self.0.difficulty.into()
+    }
+
+    async fn categories(&self) -> Vec<Category> {
+        self.0.categories.iter().copied().map(Category::from).collect()
+    }
+
+    async fn conduct(&self) -> Option<&str> {
+        self.0.conduct.as_deref()
+    }
+}
+
+// Only the course looked up by code has related courses, since each lookup runs its own query.
+struct RelatedCourses(courselib::Course);
+
+#[Object]
+impl RelatedCourses {
+    /// The most similar courses, most similar first.
+    #[graphql(complexity = "RELATED_COMPLEXITY + child_complexity")]
+    async fn related(&self) -> Result<Vec<Course>> {
+        Ok(related(&self.0).await.map_err(internal_error)?.into_iter().map(Course).collect())
+    }
+}
+
+#[derive(MergedObject)]
+struct CourseDetails(Course, RelatedCourses);
+
+impl From<courselib::Course> for CourseDetails {
+    fn from(value: courselib::Course) -> Self {
+        Self(Course(value.clone()), RelatedCourses(value))
+    }
+}
+
+// The same filters as the query string of the search page, with lists of values as GraphQL lists.
+#[derive(InputObject, Default)]
+struct CourseFilter {
+    code_pattern: Option<String>,
+    name_pattern: Option<String>,
+    points_min: Option<f32>,
+    points_max: Option<f32>,
+    #[graphql(default)]
+    points: Vec<f32>,
+    #[graphql(default)]
+    credit_units: Vec<CreditUnit>,
+    paces: Option<Vec<PaceInput>>,
+    #[graphql(default)]
+    pace_times: Vec<Time>,
+    pace_min: Option<u8>,
+    pace_max: Option<u8>,
+    #[graphql(default)]
+    periods: Vec<u8>,
+    period_from: Option<u8>,
+    period_to: Option<u8>,
+    term: Option<Term>,
+    period_select_mode: Option<PeriodSelectMode>,
+    #[graphql(default)]
+    sites: Vec<Site>,
+    #[graphql(default)]
+    languages: Vec<Language>,
+    #[graphql(default)]
+    difficulties: Vec<Difficulty>,
+    #[graphql(default)]
+    categories: Vec<Category>,
+    category_select_mode: Option<CategorySelectMode>,
+    #[graphql(default)]
+    exclude_paces: Vec<PaceInput>,
+    #[graphql(default)]
+    exclude_sites: Vec<Site>,
+    #[graphql(default)]
+    exclude_languages: Vec<Language>,
+    #[graphql(default)]
+    exclude_difficulties: Vec<Difficulty>,
+    #[graphql(default)]
+    exclude_categories: Vec<Category>,
+}
+
+fn convert<T, U: From<T>>(values: Vec<T>) -> Vec<U> {
+    values.into_iter().map(U::from).collect()
+}
+
+fn convert_paces(paces: Vec<PaceInput>) -> Result<Vec<courselib::Pace>> {
+    paces.into_iter().map(courselib::Pace::try_from).collect()
+}
+
+impl TryFrom<CourseFilter> for SearchQuery {
+    type Error = Error;
+
+    fn try_from(value: CourseFilter) -> Result<Self> {
+        Ok(Self {
+            code_pattern: value.code_pattern,
+            name_pattern: value.name_pattern,
+            points_min: value.points_min,
+            points_max: value.points_max,
+            points: value.points,
+            credit_units: convert(value.credit_units),
+            paces: value.paces.map(convert_paces).transpose()?,
+            pace_times: convert(value.pace_times),
+            pace_min: value.pace_min,
+            pace_max: value.pace_max,
+            periods: value.periods,
+            period_from: value.period_from,
+            period_to: value.period_to,
+            term: value.term.map(Into::into),
+            period_select_mode: value.period_select_mode.map(Into::into).unwrap_or_default(),
+            sites: convert(value.sites),
+            languages: convert(value.languages),
+            difficulties: convert(value.difficulties),
+            categories: convert(value.categories),
+            category_select_mode: value.category_select_mode.map(Into::into).unwrap_or_default(),
+            exclude_paces: convert_paces(value.exclude_paces)?,
+            exclude_sites: convert(value.exclude_sites),
+            exclude_languages: convert(value.exclude_languages),
+            exclude_difficulties: convert(value.exclude_difficulties),
+            exclude_categories: convert(value.exclude_categories),
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(SimpleObject)]
+struct CourseConnection {
+    total: i64,
+    courses: Vec<Course>,
+    next: Option<String>,
+    previous: Option<String>,
+}
+
+fn internal_error(e: impl ToString) -> Error {
+    eprintln!("{}", e.to_string());
+    Error::new("internal server error")
+}
+
+struct Query;
+
+#[Object]
+impl Query {
+    async fn course(&self, code: String) -> Result<Option<CourseDetails>> {
+        Ok(by_code(&code).await.map_err(internal_error)?.map(CourseDetails::from))
+    }
+
+    /// Searches either with `filter` or with `q` in the query language, which replaces the filter.
+    #[graphql(complexity = "page_size(per_page) as usize * child_complexity")]
+    async fn courses(
+        &self,
+        filter: Option<CourseFilter>,
+        q: Option<String>,
+        sort: Option<SortOrder>,
+        per_page: Option<u32>,
+        cursor: Option<String>,
+    ) -> Result<CourseConnection> {
+        let query = SearchQuery {
+            cursor: cursor
+                .map(|c| Cursor::decode(&c).ok_or_else(|| Error::new("invalid cursor")))
+                .transpose()?,
+            per_page,
+            sort: sort.map(Into::into).unwrap_or_default(),
+            ..filter.unwrap_or_default().try_into()?
+        };
+        let (conditions, pagination) = prepare(query, TextQuery { q })
+            .await
+            .map_err(|e| Error::new(e.to_string()))?;
+        let results = run_search(&conditions, &pagination).await.map_err(internal_error)?;
+
+        Ok(CourseConnection {
+            total: results.total,
+            courses: results.courses.into_iter().map(Course).collect(),
+            next: results.next.map(|c| c.to_string()),
+            previous: results.previous.map(|c| c.to_string()),
+        })
+    }
+}
+
+lazy_static! {
+    static ref SCHEMA: Schema<Query, EmptyMutation, EmptySubscription> = Schema::build(Query, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish();
+}
+
+#[post("/graphql")]
+async fn endpoint(request: web::Json<Request>) -> impl Responder {
+    HttpResponse::Ok().json(SCHEMA.execute(request.into_inner()).await)
+}
@@ -0,0 +1,165 @@
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::future::{Ready, ready};
+
+use actix_web::{FromRequest, HttpRequest, web};
+use actix_web::cookie::Cookie;
+use actix_web::dev::Payload;
+use actix_web::http::header::ACCEPT_LANGUAGE;
+use serde::{de::Error as DeError, Deserialize, Deserializer};
+
+use courselib::{Course, English};
+
+const COOKIE: &str = "lang";
+
+// The language of the web pages, chosen with the `lang` parameter, which is then remembered in a cookie, or else by `Accept-Language`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Locale {
+    #[default]
+    Swedish,
+    English,
+}
+
+impl Locale {
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            Self::Swedish => "sv",
+            Self::English => "en",
+        }
+    }
+
+    fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "sv" => Some(Self::Swedish),
+            "en" => Some(Self::English),
+            _ => None
+        }
+    }
+
+    // The language to switch to from this one.
+    pub(crate) fn other(self) -> Self {
+        match self {
+            Self::Swedish => Self::English,
+            Self::English => Self::Swedish,
+        }
+    }
+
+    // Picks one of two translations of a text in a template, like `locale.t("Poäng", "Credits")`.
+    pub(crate) fn t(self, swedish: &'static str, english: &'static str) -> &'static str {
+        match self {
+            Self::Swedish => swedish,
+            Self::English => english,
+        }
+    }
+
+    pub(crate) fn label<T: Display + English>(self, value: &T) -> String {
+        match self {
+            Self::Swedish => value.to_string(),
+            Self::English => value.english(),
+        }
+    }
+
+    // Courses without an English name keep the Swedish one.
+    pub(crate) fn name(self, course: &Course) -> &str {
+        match self {
+            Self::Swedish => &course.name_se,
+            Self::English => course.name_en.as_deref().unwrap_or(&course.name_se),
+        }
+    }
+
+    pub(crate) fn pick_name(self, name_se: String, name_en: Option<String>) -> String {
+        match self {
+            Self::Swedish => name_se,
+            Self::English => name_en.unwrap_or(name_se),
+        }
+    }
+
+    pub(crate) fn number(self, number: f32) -> String {
+        match self {
+            Self::Swedish => number.to_string().replace('.', ","),
+            Self::English => number.to_string(),
+        }
+    }
+
+    // The locale asked for with the `lang` parameter, which should be remembered for later requests.
+    pub(crate) fn requested(request: &HttpRequest) -> Option<Self> {
+        web::Query::<LocaleQuery>::from_query(request.query_string())
+            .ok()?
+            .into_inner()
+            .lang
+    }
+
+    // The current page in the other language, keeping the rest of the query string.
+    pub(crate) fn switch_link(self, request: &HttpRequest) -> String {
+        let mut pairs = serde_urlencoded::from_str::<Vec<(String, String)>>(request.query_string())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(key, _)| key != "lang")
+            .collect::<Vec<_>>();
+        pairs.push(("lang".to_owned(), self.other().code().to_owned()));
+
+        format!("{}?{}", request.path(), serde_urlencoded::to_string(pairs).unwrap())
+    }
+
+    pub(crate) fn cookie(self) -> Cookie<'static> {
+        Cookie::build(COOKIE, self.code())
+            .path("/")
+            .permanent()
+            .finish()
+    }
+
+    // Takes the preferred language among the supported ones, ignoring regions like the `SE` in `sv-SE`.
+    fn accepted(header: &str) -> Option<Self> {
+        header
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map(|q| q.parse::<f32>().ok())
+                    .unwrap_or(Some(1.))?;
+                let locale = Self::from_code(&tag.split('-').next()?.to_lowercase())?;
+                Some((locale, quality))
+            })
+            .filter(|&(_, quality)| quality > 0.)
+            .fold(None, |best: Option<(Self, f32)>, (locale, quality)| match best {
+                Some((_, best_quality)) if best_quality >= quality => best,
+                _ => Some((locale, quality)),
+            })
+            .map(|(locale, _)| locale)
+    }
+
+    pub(crate) fn of(request: &HttpRequest) -> Self {
+        Self::requested(request)
+            .or_else(|| request.cookie(COOKIE).and_then(|c| Self::from_code(c.value())))
+            .or_else(|| request
+                .headers()
+                .get(ACCEPT_LANGUAGE)
+                .and_then(|h| h.to_str().ok())
+                .and_then(Self::accepted))
+            .unwrap_or_default()
+    }
+}
+
+impl<'de> Deserialize<'de> for Locale {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        let code = String::deserialize(deserializer)?;
+        Self::from_code(&code).ok_or_else(|| DeError::unknown_variant(&code, &["sv", "en"]))
+    }
+}
+
+impl FromRequest for Locale {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(Self::of(request)))
+    }
+}
+
+#[derive(Deserialize)]
+struct LocaleQuery {
+    #[serde(default)]
+    lang: Option<Locale>,
+}
@@ -0,0 +1,168 @@
+use serde_json::{json, Value};
+
+use courselib::{Course, Site};
+
+use super::locale::Locale;
+
+const PROVIDER: &str = "Luleå tekniska universitet";
+const PROVIDER_URL: &str = "https://www.ltu.se";
+
+// Describes a course for search engines, see https://schema.org/Course.
+pub(crate) fn json_ld(course: &Course, locale: Locale) -> Value {
+    let name = locale.pick_name(course.name_se.clone(), course.name_en.clone());
+    let other_name = match locale {
+        Locale::Swedish => course.name_en.clone(),
+        Locale::English => Some(course.name_se.clone()),
+    };
+
+    let mut instance = json!({
+        "@type": "CourseInstance",
+        "courseMode": match course.site {
+            Some(Site::LocationIndependent) => "online",
+            _ => "onsite",
+        },
+    });
+    if let Some(site) = course.site.filter(|&s| s != Site::LocationIndependent) {
+        instance["location"] = json!({
+            "@type": "Place",
+            "name": locale.label(&site),
+        });
+    }
+    if let Some(start) = course.start_date {
+        instance["startDate"] = json!(start.to_string());
+    }
+    if let Some(end) = course.end_date {
+        instance["endDate"] = json!(end.to_string());
+    }
+
+    let mut course_ld = json!({
+        "@context": "https://schema.org",
+        "@type": "Course",
+        "courseCode": course.code,
+        "name": name,
+        "url": course.url,
+        "numberOfCredits": {
+            "@type": "StructuredValue",
+            "value": course.points,
+            "unitText": locale.label(&course.credit_unit),
+        },
+        "educationalLevel": locale.label(&course.difficulty),
+        "provider": {
+            "@type": "CollegeOrUniversity",
+            "name": PROVIDER,
+            "sameAs": PROVIDER_URL,
+        },
+        "hasCourseInstance": [instance],
+    });
+    if let Some(other_name) = other_name.filter(|n| n != &name) {
+        course_ld["alternateName"] = json!(other_name);
+    }
+    if let Some(language) = course.language {
+        course_ld["inLanguage"] = json!(language.code());
+    }
+    if let Some(prerequisites) = &course.prerequisites {
+        course_ld["coursePrerequisites"] = json!(prerequisites);
+    }
+
+    course_ld
+}
+
+// JSON to put in a `<script>` element, which mustn't contain anything that looks like a closing tag.
+pub(crate) fn script(value: &Value) -> String {
+    value.to_string().replace('<', "\\u003c")
+}
+
+// One line for link previews, like "D0015E, 7,5 högskolepoäng, Grundnivå, Luleå".
+pub(crate) fn description(course: &Course, locale: Locale) -> String {
+    [
+        Some(course.code.clone()),
+        Some(format!("{} {}", locale.number(course.points), locale.label(&course.credit_unit))),
+        Some(locale.label(&course.difficulty)),
+        course.site.map(|s| locale.label(&s)),
+    ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use courselib::{Categories, Category, CreditUnit, Difficulty, Language};
+
+    use super::*;
+
+    fn course() -> Course {
+        Course {
+            code: "D0015E".to_owned(),
+            name_se: "Objektorienterad programmering".to_owned(),
+            name_en: Some("Object oriented programming".to_owned()),
+            url: "https://www.ltu.se/edu/course/D00/D0015E".to_owned(),
+            points: 7.5,
+            credit_unit: CreditUnit::HigherEducation,
+            pace: None,
+            prerequisites: Some("Grundläggande behörighet </script>".to_owned()),
+            register_info: None,
+            modules: None,
+            period_start: Some(1),
+            period_end: Some(2),
+            start_date: NaiveDate::from_ymd_opt(2024, 9, 2),
+            end_date: NaiveDate::from_ymd_opt(2024, 11, 3),
+            site: Some(Site::Lulea),
+            language: Some(Language::Swedish),
+            difficulty: Difficulty::Undergraduate,
+            categories: Categories::from(vec![Category::Data]),
+            conduct: None,
+        }
+    }
+
+    #[test]
+    fn course_structure() {
+        let ld = json_ld(&course(), Locale::English);
+
+        assert_eq!(ld["@context"], "https://schema.org");
+        assert_eq!(ld["@type"], "Course");
+        assert_eq!(ld["courseCode"], "D0015E");
+        assert_eq!(ld["name"], "Object oriented programming");
+        assert_eq!(ld["alternateName"], "Objektorienterad programmering");
+        assert_eq!(ld["inLanguage"], "sv");
+        assert_eq!(ld["educationalLevel"], "First cycle");
+        assert_eq!(ld["numberOfCredits"]["@type"], "StructuredValue");
+        assert_eq!(ld["numberOfCredits"]["value"], 7.5);
+        assert_eq!(ld["numberOfCredits"]["unitText"], "credits");
+        assert_eq!(ld["provider"]["@type"], "CollegeOrUniversity");
+        assert_eq!(ld["provider"]["name"], PROVIDER);
+    }
+
+    #[test]
+    fn instance_structure() {
+        let ld = json_ld(&course(), Locale::Swedish);
+        let instances = ld["hasCourseInstance"].as_array().expect("instances should be an array");
+
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0]["@type"], "CourseInstance");
+        assert_eq!(instances[0]["courseMode"], "onsite");
+        assert_eq!(instances[0]["location"]["@type"], "Place");
+        assert_eq!(instances[0]["location"]["name"], "Luleå");
+        assert_eq!(instances[0]["startDate"], "2024-09-02");
+        assert_eq!(instances[0]["endDate"], "2024-11-03");
+    }
+
+    #[test]
+    fn location_independent_is_online() {
+        let ld = json_ld(&Course { site: Some(Site::LocationIndependent), ..course() }, Locale::Swedish);
+
+        assert_eq!(ld["hasCourseInstance"][0]["courseMode"], "online");
+        assert!(ld["hasCourseInstance"][0].get("location").is_none());
+    }
+
+    #[test]
+    fn script_cannot_close_element() {
+        let script = script(&json_ld(&course(), Locale::Swedish));
+
+        assert!(!script.contains("</"));
+        assert_eq!(serde_json::from_str::<Value>(&script).unwrap(), json_ld(&course(), Locale::Swedish));
+    }
+}
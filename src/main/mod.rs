@@ -1,12 +1,36 @@
 use std::io::Result as IoResult;
-use actix_web::{App, HttpServer};
+use actix_web::{App, HttpServer, web};
+use actix_web::dev::Service;
 use sqlx::PgPool;
 use tokio::sync::OnceCell;
 
-use course::page as course_page;
+use api::{course as api_course, create_saved as api_create_saved, query_config as api_query_config, saved_search as api_saved_search, search as api_search};
+use autocomplete::{spawn_updater, suggest as api_autocomplete};
+use calendar::export as calendar_export;
+use compare::page as compare_page;
+use course::{page as course_page, redirect as course_redirect};
+use error::not_found;
+use graphql::endpoint as graphql_endpoint;
+use locale::Locale;
+use openapi::{document as openapi_document, viewer as openapi_viewer};
+use plan::{add as add_to_plan, copy_saved as copy_saved_plan, create_saved as create_saved_plan, page as plan_page, remove as remove_from_plan, saved_page as saved_plan_page};
+use saved::{create as create_saved, page as saved_page};
 use search::page as search_page;
 
+mod api;
+mod autocomplete;
+mod cache;
+mod calendar;
+mod compare;
 mod course;
+mod error;
+mod format;
+mod graphql;
+mod locale;
+mod metadata;
+mod openapi;
+mod plan;
+mod saved;
 mod search;
 
 static CONNECTION: OnceCell<PgPool> = OnceCell::const_new();
@@ -19,11 +43,48 @@ async fn main() -> IoResult<()> {
             .expect("failed to connect to db")
     }).await;
 
+    spawn_updater();
+
     HttpServer::new(||
         App::new()
+            // A language chosen with the `lang` parameter is remembered for the following pages.
+            .wrap_fn(|request, service| {
+                let locale = Locale::requested(request.request());
+                let response = service.call(request);
+                async move {
+                    let mut response = response.await?;
+                    if let Some(locale) = locale {
+                        response.response_mut().add_cookie(&locale.cookie())?;
+                    }
+                    Ok(response)
+                }
+            })
             .service(search_page)
+            // Before the course page, whose code would otherwise match the extension too.
+            .service(calendar_export)
             .service(course_page)
-            // TODO: error and 404
+            .service(course_redirect)
+            .service(compare_page)
+            .service(create_saved)
+            .service(saved_page)
+            .service(plan_page)
+            // Before the saved plans, whose slugs would otherwise match these.
+            .service(add_to_plan)
+            .service(remove_from_plan)
+            .service(create_saved_plan)
+            .service(saved_plan_page)
+            .service(copy_saved_plan)
+            .service(graphql_endpoint)
+            .service(web::scope("/api/v1")
+                .app_data(api_query_config())
+                .service(api_search)
+                .service(api_course)
+                .service(api_autocomplete)
+                .service(api_create_saved)
+                .service(api_saved_search))
+            .service(openapi_document)
+            .service(openapi_viewer)
+            .default_service(web::to(not_found))
     )
         .bind(("127.0.0.1", 8080))?
         .run()
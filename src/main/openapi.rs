@@ -0,0 +1,33 @@
+use actix_web::{get, HttpResponse, Responder};
+use utoipa::OpenApi;
+
+use courselib::{Categories, Category, Course, CreditUnit, Difficulty, Language, Pace, Site, Time};
+
+use super::api::{self, ErrorResponse, SavedSearchResponse, SearchResponse};
+use super::autocomplete::{self, Suggestion};
+use super::saved::SavedSearch;
+use super::search::{CategorySelectMode, Facets, PeriodSelectMode, SortOrder, Term};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Kurser", description = "Search and look up courses."),
+    paths(api::search, api::course, api::create_saved, api::saved_search, autocomplete::suggest),
+    components(schemas(
+        Course, CreditUnit, Pace, Time, Site, Language, Difficulty, Category, Categories,
+        SortOrder, Term, PeriodSelectMode, CategorySelectMode,
+        Facets, SearchResponse, ErrorResponse, Suggestion, SavedSearch, SavedSearchResponse,
+    )),
+)]
+struct ApiDoc;
+
+#[get("/api/openapi.json")]
+async fn document() -> impl Responder {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+#[get("/api/docs")]
+async fn viewer() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(include_str!("../../html/openapi.html"))
+}
@@ -0,0 +1,418 @@
+use actix_web::{get, HttpRequest, HttpResponse, post, web};
+use actix_web::cookie::Cookie;
+use actix_web::http::header::LOCATION;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Error as SqlxError, query_as};
+
+use courselib::Course;
+
+use super::CONNECTION;
+use super::compare::by_codes;
+use super::course::{by_code, periods};
+use super::error::{PageError, parse_query};
+use super::locale::Locale;
+use super::saved::save_under_slug;
+use super::search::{comma_separated, Term};
+
+// The plan being worked on is kept in a cookie, and saved plans get slugs the same way as saved searches.
+
+const COOKIE: &str = "plan";
+const MAX_COURSES: usize = 20;
+const FULL_TIME: i32 = 100;
+
+// The course codes planned for each term, written like a query string, as in `ht=D0015E,D0010E&vt=E0001E`.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+struct StudyPlan {
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Vec::is_empty")]
+    ht: Vec<String>,
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Vec::is_empty")]
+    vt: Vec<String>,
+}
+
+impl StudyPlan {
+    fn parse(text: &str) -> Option<Self> {
+        serde_urlencoded::from_str(text).ok()
+    }
+
+    fn to_text(&self) -> String {
+        serde_urlencoded::to_string(self).unwrap()
+    }
+
+    // A plan which can't be read, perhaps from an older version, is started over.
+    fn current(request: &HttpRequest) -> Self {
+        request
+            .cookie(COOKIE)
+            .and_then(|c| Self::parse(c.value()))
+            .unwrap_or_default()
+    }
+
+    fn term(&mut self, term: Term) -> &mut Vec<String> {
+        match term {
+            Term::Autumn => &mut self.ht,
+            Term::Spring => &mut self.vt,
+        }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = (Term, &String)> {
+        self.ht
+            .iter()
+            .map(|c| (Term::Autumn, c))
+            .chain(self.vt.iter().map(|c| (Term::Spring, c)))
+    }
+
+    fn remove(&mut self, code: &str) {
+        self.ht.retain(|c| c != code);
+        self.vt.retain(|c| c != code);
+    }
+
+    // Goes back to the plan with it stored in the cookie.
+    fn stored(&self) -> HttpResponse {
+        HttpResponse::SeeOther()
+            .insert_header((LOCATION, "/plan"))
+            .cookie(Cookie::build(COOKIE, self.to_text()).path("/").permanent().finish())
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SavedPlan {
+    slug: String,
+    plan: String,
+    created_at: DateTime<Utc>,
+}
+
+async fn save(plan: &StudyPlan) -> Result<SavedPlan, SqlxError> {
+    let text = plan.to_text();
+
+    let insert = |slug: String| {
+        // The no-op update makes an existing row with the slug get returned.
+        query_as!(
+            SavedPlan,
+            "INSERT INTO study_plans (slug, plan) VALUES ($1, $2)
+            ON CONFLICT (slug) DO UPDATE SET slug = EXCLUDED.slug
+            RETURNING slug, plan, created_at",
+            slug,
+            text
+        )
+            .fetch_one(CONNECTION.get().unwrap())
+    };
+
+    save_under_slug("study plan", &text, insert, |saved| &saved.plan).await
+}
+
+async fn load(slug: &str) -> Result<Option<SavedPlan>, SqlxError> {
+    query_as!(
+        SavedPlan,
+        "SELECT slug, plan, created_at FROM study_plans WHERE slug = $1",
+        slug
+    )
+        .fetch_optional(CONNECTION.get().unwrap())
+        .await
+}
+
+struct PlannedCourse {
+    code: String,
+    name: String,
+    points: String,
+    pace: Option<String>,
+    periods: Option<String>,
+    // Whether the course runs within the periods of its term, if its periods are known.
+    fits: Option<bool>,
+}
+
+struct PeriodLoad {
+    period: i16,
+    credits: String,
+    percentage: i32,
+}
+
+#[derive(Template)]
+#[template(path = "../html/plan.html")]
+struct PlanTemplate {
+    locale: Locale,
+    switch_language: String,
+    // The term codes, names and courses.
+    terms: Vec<(&'static str, &'static str, Vec<PlannedCourse>)>,
+    loads: Vec<PeriodLoad>,
+    full_time: i32,
+    // Periods with more than full time studies.
+    overloaded: String,
+    // Only the plan in the cookie can be changed, saved plans are copied to it first.
+    editable: bool,
+    // When a saved plan was saved, and where it can be copied from.
+    saved: Option<(String, String)>,
+}
+
+impl PlanTemplate {
+    fn new(plan: &StudyPlan, mut courses: Vec<Course>, locale: Locale, request: &HttpRequest) -> Self {
+        let mut loads = (1..=4)
+            .map(|period| (period, 0., 0))
+            .collect::<Vec<(i16, f32, i32)>>();
+        let mut autumn = Vec::new();
+        let mut spring = Vec::new();
+
+        // Codes of courses which have since been removed from the index are left out.
+        for (term, code) in plan.entries() {
+            let Some(i) = courses.iter().position(|c| &c.code == code) else { continue };
+            let course = courses.swap_remove(i);
+            let study_periods = course.study_periods();
+
+            // Courses which wrap over the summer are counted in the periods at both ends of the academic year.
+            for &period in &study_periods {
+                if let Some((_, credits, percentage)) = loads.iter_mut().find(|(p, ..)| *p == period) {
+                    *credits += course.points / study_periods.len() as f32;
+                    *percentage += course.pace.map(|p| *p.percentage() as i32).unwrap_or_default();
+                }
+            }
+
+            let planned = PlannedCourse {
+                name: locale.name(&course).to_owned(),
+                points: format!("{} {}", locale.number(course.points), locale.label(&course.credit_unit)),
+                pace: course.pace.map(|p| locale.label(&p)),
+                periods: periods(&course, locale),
+                fits: (!study_periods.is_empty()).then(|| study_periods
+                    .iter()
+                    .all(|&p| term.periods().contains(&(p as u8)))),
+                code: course.code,
+            };
+            match term {
+                Term::Autumn => autumn.push(planned),
+                Term::Spring => spring.push(planned),
+            }
+        }
+
+        let overloaded = loads
+            .iter()
+            .filter(|&&(_, _, percentage)| percentage > FULL_TIME)
+            .map(|(period, ..)| period.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        Self {
+            locale,
+            switch_language: locale.switch_link(request),
+            overloaded,
+            terms: vec![
+                (Term::Autumn.code(), locale.t("Hösttermin", "Autumn term"), autumn),
+                (Term::Spring.code(), locale.t("Vårtermin", "Spring term"), spring),
+            ],
+            loads: loads
+                .into_iter()
+                .map(|(period, credits, percentage)| PeriodLoad {
+                    period,
+                    credits: locale.number((credits * 10.).round() / 10.),
+                    percentage,
+                })
+                .collect(),
+            full_time: FULL_TIME,
+            editable: true,
+            saved: None,
+        }
+    }
+}
+
+async fn render(plan: &StudyPlan, locale: Locale, request: &HttpRequest) -> Result<PlanTemplate, PageError> {
+    let codes = plan.entries().map(|(_, c)| c.clone()).collect::<Vec<_>>();
+    let courses = by_codes(&codes).await.map_err(|e| PageError::internal(locale, e))?;
+
+    Ok(PlanTemplate::new(plan, courses, locale, request))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Addition {
+    code: String,
+    term: Term,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Removal {
+    code: String,
+}
+
+#[get("/plan")]
+async fn page(locale: Locale, request: HttpRequest) -> Result<HttpResponse, PageError> {
+    let template = render(&StudyPlan::current(&request), locale, &request).await?;
+
+    Ok(HttpResponse::Ok()
+        .body(template.render().unwrap()))
+}
+
+// Courses already in the plan are moved to the new term.
+#[post("/plan/add")]
+async fn add(form: String, locale: Locale, request: HttpRequest) -> Result<HttpResponse, PageError> {
+    let addition = parse_query::<Addition>(locale, &form)?;
+    let course = by_code(addition.code.trim())
+        .await
+        .map_err(|e| PageError::internal(locale, e))?
+        .ok_or_else(|| PageError::unknown_course(locale, &addition.code))?;
+
+    let mut plan = StudyPlan::current(&request);
+    plan.remove(&course.code);
+    if plan.entries().count() >= MAX_COURSES {
+        return Err(PageError::invalid_parameter(
+            locale,
+            "code",
+            format_args!("study plans can have at most {} courses", MAX_COURSES)
+        ))
+    }
+    plan.term(addition.term).push(course.code);
+
+    Ok(plan.stored())
+}
+
+#[post("/plan/remove")]
+async fn remove(form: String, locale: Locale, request: HttpRequest) -> Result<HttpResponse, PageError> {
+    let removal = parse_query::<Removal>(locale, &form)?;
+
+    let mut plan = StudyPlan::current(&request);
+    plan.remove(&removal.code);
+
+    Ok(plan.stored())
+}
+
+#[post("/plan/save")]
+async fn create_saved(locale: Locale, request: HttpRequest) -> Result<HttpResponse, PageError> {
+    let saved = save(&StudyPlan::current(&request)).await.map_err(|e| PageError::internal(locale, e))?;
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, format!("/plan/{}", saved.slug)))
+        .finish())
+}
+
+async fn load_plan(slug: &str, locale: Locale) -> Result<(SavedPlan, StudyPlan), PageError> {
+    let saved = load(slug)
+        .await
+        .map_err(|e| PageError::internal(locale, e))?
+        .ok_or_else(|| PageError::unknown_page(locale))?;
+    let plan = StudyPlan::parse(&saved.plan)
+        .ok_or_else(|| PageError::internal(locale, format_args!("invalid study plan {}", saved.slug)))?;
+
+    Ok((saved, plan))
+}
+
+#[get("/plan/{slug}")]
+async fn saved_page(slug: web::Path<String>, locale: Locale, request: HttpRequest) -> Result<HttpResponse, PageError> {
+    let (saved, plan) = load_plan(&slug, locale).await?;
+
+    let mut template = render(&plan, locale, &request).await?;
+    template.editable = false;
+    template.saved = Some((saved.created_at.format("%Y-%m-%d %H:%M UTC").to_string(), format!("/plan/{}", saved.slug)));
+
+    Ok(HttpResponse::Ok()
+        .body(template.render().unwrap()))
+}
+
+// Replaces the plan in the cookie with a saved one.
+#[post("/plan/{slug}")]
+async fn copy_saved(slug: web::Path<String>, locale: Locale) -> Result<HttpResponse, PageError> {
+    let (_, plan) = load_plan(&slug, locale).await?;
+
+    Ok(plan.stored())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use courselib::{Categories, CreditUnit, Difficulty, Pace, Time};
+
+    use super::*;
+
+    fn course(code: &str, points: f32, pace: Option<Pace>, periods: (Option<i16>, Option<i16>)) -> Course {
+        Course {
+            code: code.to_owned(),
+            name_se: code.to_owned(),
+            name_en: None,
+            url: String::new(),
+            points,
+            credit_unit: CreditUnit::HigherEducation,
+            pace,
+            prerequisites: None,
+            register_info: None,
+            modules: None,
+            period_start: periods.0,
+            period_end: periods.1,
+            start_date: None,
+            end_date: None,
+            site: None,
+            language: None,
+            difficulty: Difficulty::Undergraduate,
+            categories: Categories::from(Vec::new()),
+            conduct: None,
+        }
+    }
+
+    fn day(percentage: i8) -> Option<Pace> {
+        Some(Pace::new(Time::Day, percentage.try_into().unwrap()))
+    }
+
+    fn template(plan: &StudyPlan, courses: Vec<Course>) -> PlanTemplate {
+        PlanTemplate::new(plan, courses, Locale::English, &TestRequest::default().uri("/plan").to_http_request())
+    }
+
+    fn plan(ht: &[&str], vt: &[&str]) -> StudyPlan {
+        StudyPlan {
+            ht: ht.iter().map(|&c| c.to_owned()).collect(),
+            vt: vt.iter().map(|&c| c.to_owned()).collect(),
+        }
+    }
+
+    #[test]
+    fn cookie_round_trip() {
+        let plan = plan(&["D0015E", "D0010E"], &["E0001E"]);
+        assert_eq!(plan.to_text(), "ht=D0015E%2CD0010E&vt=E0001E");
+        assert_eq!(StudyPlan::parse(&plan.to_text()), Some(plan));
+
+        assert_eq!(StudyPlan::parse("vt=E0001E,D0015E"), Some(self::plan(&[], &["E0001E", "D0015E"])));
+        assert_eq!(StudyPlan::default().to_text(), "");
+        assert_eq!(StudyPlan::parse(""), Some(StudyPlan::default()));
+    }
+
+    #[test]
+    fn fitting_terms() {
+        let courses = || vec![
+            course("A0001A", 7.5, day(50), (Some(1), Some(2))),
+            course("B0001B", 7.5, day(50), (Some(3), Some(3))),
+            course("C0001C", 7.5, day(50), (None, None)),
+        ];
+        let fits = |template: PlanTemplate| template.terms
+            .into_iter()
+            .flat_map(|(term, _, courses)| courses.into_iter().map(move |c| (term, c.code, c.fits)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(fits(template(&plan(&["A0001A", "B0001B", "C0001C"], &[]), courses())), [
+            ("ht", "A0001A".to_owned(), Some(true)),
+            ("ht", "B0001B".to_owned(), Some(false)),
+            ("ht", "C0001C".to_owned(), None),
+        ]);
+        assert_eq!(fits(template(&plan(&[], &["A0001A", "B0001B", "C0001C"]), courses())), [
+            ("vt", "A0001A".to_owned(), Some(false)),
+            ("vt", "B0001B".to_owned(), Some(true)),
+            ("vt", "C0001C".to_owned(), None),
+        ]);
+    }
+
+    #[test]
+    fn loads_per_period() {
+        let courses = vec![
+            course("A0001A", 7.5, day(50), (Some(1), Some(2))),
+            // Wraps from the end of the academic year into the start of the next.
+            course("B0001B", 15., day(100), (Some(4), Some(1))),
+            course("C0001C", 7.5, None, (Some(3), Some(3))),
+        ];
+        let template = template(&plan(&["A0001A"], &["B0001B", "C0001C", "X0001X"]), courses);
+
+        let loads = template.loads
+            .iter()
+            .map(|l| (l.period, l.credits.as_str(), l.percentage))
+            .collect::<Vec<_>>();
+        assert_eq!(loads, [(1, "11.3", 150), (2, "3.8", 50), (3, "7.5", 0), (4, "7.5", 100)]);
+        assert_eq!(template.overloaded, "1");
+        assert_eq!(template.full_time, FULL_TIME);
+
+        // Courses missing from the index are left out.
+        assert_eq!(template.terms[1].2.iter().map(|c| c.code.as_str()).collect::<Vec<_>>(), ["B0001B", "C0001C"]);
+    }
+}
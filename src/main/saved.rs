@@ -0,0 +1,192 @@
+use std::future::Future;
+
+use actix_web::{get, HttpRequest, HttpResponse, post, web};
+use actix_web::http::header::LOCATION;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Error as SqlxError, query_as};
+use utoipa::ToSchema;
+
+use super::CONNECTION;
+use super::error::{PageError, parse_query};
+use super::locale::Locale;
+use super::search::{merge, prepare, QueryError, search, SearchQuery, SearchTemplate, TextQuery};
+
+// Searches are saved in the query language rather than as query strings, since it is the more stable format of the two.
+// The slug is derived from the query, so saving the same search twice gives the same link.
+
+const SLUG_LENGTH: usize = 8;
+
+#[derive(Serialize, ToSchema, Debug, Clone)]
+pub(crate) struct SavedSearch {
+    pub(crate) slug: String,
+    /// The search in the query language.
+    pub(crate) query: String,
+    #[schema(value_type = String, format = DateTime)]
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+// FNV-1a, which unlike the standard library hashers is guaranteed to stay the same between releases.
+pub(crate) fn hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+// All 13 base 36 digits of the hash, of which the first `SLUG_LENGTH` are used unless they collide with another slug.
+pub(crate) fn digits(mut hash: u64) -> String {
+    let mut digits = [b'0'; 13];
+    for d in digits.iter_mut().rev() {
+        *d = b"0123456789abcdefghijklmnopqrstuvwxyz"[(hash % 36) as usize];
+        hash /= 36;
+    }
+    String::from_utf8(digits.to_vec()).unwrap()
+}
+
+// Stores `text` with `insert` under the first of its slugs that is free or already holds the same text.
+// `insert` gives back the row which has the slug afterwards, and `text_of` the text in that row.
+pub(crate) async fn save_under_slug<T, F>(kind: &str, text: &str, insert: impl Fn(String) -> F, text_of: impl Fn(&T) -> &str) -> Result<T, SqlxError>
+where F: Future<Output = Result<T, SqlxError>> {
+    let digits = digits(hash(text));
+
+    for length in SLUG_LENGTH..=digits.len() {
+        let saved = insert(digits[..length].to_owned()).await?;
+        if text_of(&saved) == text {
+            return Ok(saved);
+        }
+    }
+
+    Err(SqlxError::Protocol(format!("no free slug for {} {}", kind, text)))
+}
+
+// Queries must be checked with `validate` first, since they can't be corrected once saved.
+pub(crate) async fn save(query: &SearchQuery) -> Result<SavedSearch, SqlxError> {
+    debug_assert!(round_trips(query), "saving a search which reads back differently: {:?}", query);
+    let text = query.to_string();
+
+    let insert = |slug: String| {
+        // The no-op update makes an existing row with the slug get returned.
+        query_as!(
+            SavedSearch,
+            "INSERT INTO saved_searches (slug, query) VALUES ($1, $2)
+            ON CONFLICT (slug) DO UPDATE SET slug = EXCLUDED.slug
+            RETURNING slug, query, created_at",
+            slug,
+            text
+        )
+            .fetch_one(CONNECTION.get().unwrap())
+    };
+
+    save_under_slug("saved search", &text, insert, |saved| &saved.query).await
+}
+
+// Writes the parts of a search that the query language would write differently the way it reads them back.
+// Paging isn't saved, and neither are quotes in name patterns, which are ignored when searching anyway.
+fn normalize(query: SearchQuery) -> SearchQuery {
+    let (period_from, period_to) = match (query.period_from, query.period_to) {
+        (None, None) => (None, None),
+        (from, to) => (Some(from.unwrap_or(1)), Some(to.unwrap_or(4))),
+    };
+
+    SearchQuery {
+        cursor: None,
+        per_page: None,
+        code_pattern: query.code_pattern.map(|p| p.trim().to_owned()),
+        name_pattern: query.name_pattern.map(|p| p.replace('"', "")),
+        paces: query.paces.filter(|p| !p.is_empty()),
+        period_from,
+        period_to,
+        ..query
+    }
+}
+
+fn round_trips(query: &SearchQuery) -> bool {
+    query.to_string().parse::<SearchQuery>().is_ok_and(|q| q == *query)
+}
+
+// Gives the query that will be saved, which reads back the same from the query language.
+pub(crate) async fn validate(query: SearchQuery, text: TextQuery) -> Result<SearchQuery, QueryError> {
+    let query = normalize(merge(query, text)?);
+    prepare(query.clone(), TextQuery { q: None }).await?;
+    if !round_trips(&query) {
+        return Err(QueryError::Unsaveable)
+    }
+    Ok(query)
+}
+
+pub(crate) async fn load(slug: &str) -> Result<Option<SavedSearch>, SqlxError> {
+    query_as!(
+        SavedSearch,
+        "SELECT slug, query, created_at FROM saved_searches WHERE slug = $1",
+        slug
+    )
+        .fetch_optional(CONNECTION.get().unwrap())
+        .await
+}
+
+#[post("/saved")]
+async fn create(locale: Locale, request: HttpRequest) -> Result<HttpResponse, PageError> {
+    let query = parse_query::<SearchQuery>(locale, request.query_string())?;
+    let text = parse_query::<TextQuery>(locale, request.query_string())?;
+    let with_text = text.q.is_some();
+
+    let query = validate(query, text).await.map_err(|e| PageError::invalid_query(locale, e, with_text))?;
+    let saved = save(&query).await.map_err(|e| PageError::internal(locale, e))?;
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, format!("/saved/{}", saved.slug)))
+        .finish())
+}
+
+// Only the paging parameters of the query string are used, the filters come from the saved search.
+#[get("/saved/{slug}")]
+async fn page(slug: web::Path<String>, locale: Locale, request: HttpRequest) -> Result<HttpResponse, PageError> {
+    let query = parse_query::<SearchQuery>(locale, request.query_string())?;
+    let saved = load(&slug)
+        .await
+        .map_err(|e| PageError::internal(locale, e))?
+        .ok_or_else(|| PageError::unknown_saved_search(locale, &slug))?;
+
+    let paging = SearchQuery {
+        per_page: query.per_page,
+        ..Default::default()
+    };
+    let (conditions, pagination) = prepare(query, TextQuery { q: Some(saved.query) })
+        .await
+        .map_err(|e| PageError::invalid_query(locale, e, true))?;
+    let results = search(&conditions, &pagination).await.map_err(|e| PageError::internal(locale, e))?;
+
+    let mut template = SearchTemplate::new(results, pagination.per_page, &paging, locale, &request);
+    template.saved = Some(saved.created_at.format("%Y-%m-%d %H:%M UTC").to_string());
+
+    Ok(HttpResponse::Ok()
+        .body(template.render().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalized_queries_round_trip() {
+        let query = SearchQuery {
+            per_page: Some(50),
+            code_pattern: Some(" D7* ".to_owned()),
+            name_pattern: Some("\"machine\" learning".to_owned()),
+            paces: Some(Vec::new()),
+            period_from: Some(3),
+            ..Default::default()
+        };
+        assert!(!round_trips(&query));
+
+        let normalized = normalize(query);
+        assert_eq!(normalized.code_pattern.as_deref(), Some("D7*"));
+        assert_eq!((normalized.period_from, normalized.period_to), (Some(3), Some(4)));
+        assert!(round_trips(&normalized));
+    }
+
+    #[test]
+    fn numbers_that_read_back_differently() {
+        let query = SearchQuery { points: vec![f32::NAN], ..Default::default() };
+        assert!(!round_trips(&normalize(query)));
+    }
+}
@@ -1,66 +1,414 @@
-use actix_web::{get, HttpResponse, Responder, web};
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::iter::once;
+use std::ops::RangeInclusive;
+
+use actix_web::{get, HttpRequest, HttpResponse};
+use askama::Template;
 use itertools::Itertools;
 use lazy_static::lazy_static;
 use regex::Regex;
-use serde::{de::Error as DeError, Deserialize, Deserializer};
-use sqlx::QueryBuilder;
+use serde::{de::Error as DeError, Deserialize, Deserializer, Serialize, Serializer};
+use split_first_char::SplitFirstChar;
+use sqlx::{Error as SqlxError, FromRow, query_as, query_scalar};
+use tokio::try_join;
+use utoipa::{IntoParams, ToSchema};
+use utoipa::openapi::{ObjectBuilder, RefOr, Schema, SchemaType};
 
-use courselib::{Category, Course, Difficulty, Language, Pace, Site};
+use courselib::{Category, Course, CreditUnit, Difficulty, Language, Pace, Site, Time};
 
+use query_language::ParseError;
 use super::CONNECTION;
+use super::api::SearchResponse;
+use super::cache::{changed, not_modified, Page};
+use super::error::{PageError, parse_query};
+use super::format::{csv, Format, search_markdown};
+use super::locale::Locale;
+
+pub(crate) mod comma_separated;
+mod query_language;
 
 const ITEMS_PER_PAGE: u32 = 20;
+const MAX_ITEMS_PER_PAGE: u32 = 100;
 
 lazy_static! {
-    static ref CODE_PAT_VALIDATE: Regex = Regex::new(r"^[A-Z\d_]{6}$").expect("failed to parse regex");
     static ref SPECIAL_CHARACTERS: Regex = Regex::new(r"[^\pL\d\s]").expect("failed to parse regex");
 }
 
-#[derive(Deserialize, Debug, Clone)]
-struct SearchQuery {
-    #[serde(default)]
-    page: u32,
-    #[serde(default)]
-    code_pattern: Option<String>,
-    #[serde(default)]
-    name_pattern: Option<String>,
-    #[serde(default)]
-    points: Option<f32>,
-    #[serde(default)]
-    paces: Option<Vec<Pace>>,
+#[derive(Deserialize, Serialize, IntoParams, Debug, Default, Clone, PartialEq)]
+#[into_params(parameter_in = Query, style = Form)]
+pub(crate) struct SearchQuery {
+    /// Continues from the `next` or `previous` cursor of an earlier response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[param(value_type = Option<String>)]
+    pub(crate) cursor: Option<Cursor>,
+    /// Results per page, at most 100.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) per_page: Option<u32>,
+    /// Order of the results, ties are broken by insertion order.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub(crate) sort: SortOrder,
+    /// Course code pattern, where `?` matches one character and `*` any number of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) code_pattern: Option<String>,
+    /// Text to find in the Swedish or English name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) name_pattern: Option<String>,
+    /// Least number of credits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) points_min: Option<f32>,
+    /// Greatest number of credits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) points_max: Option<f32>,
+    /// Exact numbers of credits.
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Vec::is_empty")]
+    #[param(explode = false)]
+    pub(crate) points: Vec<f32>,
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Vec::is_empty")]
+    #[param(explode = false)]
+    pub(crate) credit_units: Vec<CreditUnit>,
+    #[serde(default, with = "comma_separated::optional", skip_serializing_if = "Option::is_none")]
+    #[param(explode = false)]
+    pub(crate) paces: Option<Vec<Pace>>,
+    /// Teaching times of the pace, regardless of its percentage.
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Vec::is_empty")]
+    #[param(explode = false)]
+    pub(crate) pace_times: Vec<Time>,
+    /// Least percentage of full time studies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pace_min: Option<u8>,
+    /// Greatest percentage of full time studies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pace_max: Option<u8>,
     // TODO: modules?
-    #[serde(default)]
-    period: Option<u8>,
-    #[serde(default)]
-    period_select_mode: PeriodSelectMode,
-    #[serde(default)]
-    sites: Vec<Site>,
-    #[serde(default)]
-    languages: Vec<Language>,
-    #[serde(default)]
-    difficulties: Vec<Difficulty>,
-    #[serde(default)]
-    categories: Vec<Category>,
-    #[serde(default)]
-    category_select_mode: CategorySelectMode,
+    /// Periods 1 to 4, combined with the range and term.
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Vec::is_empty")]
+    #[param(explode = false)]
+    pub(crate) periods: Vec<u8>,
+    /// Start of a period range, which wraps over the summer if it is after the end.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) period_from: Option<u8>,
+    /// End of a period range.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) period_to: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) term: Option<Term>,
+    /// How courses must relate to the selected periods.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub(crate) period_select_mode: PeriodSelectMode,
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Vec::is_empty")]
+    #[param(explode = false)]
+    pub(crate) sites: Vec<Site>,
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Vec::is_empty")]
+    #[param(explode = false)]
+    pub(crate) languages: Vec<Language>,
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Vec::is_empty")]
+    #[param(explode = false)]
+    pub(crate) difficulties: Vec<Difficulty>,
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Vec::is_empty")]
+    #[param(explode = false)]
+    pub(crate) categories: Vec<Category>,
+    /// Whether courses must have any, all or none of the categories.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub(crate) category_select_mode: CategorySelectMode,
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Vec::is_empty")]
+    #[param(explode = false)]
+    pub(crate) exclude_paces: Vec<Pace>,
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Vec::is_empty")]
+    #[param(explode = false)]
+    pub(crate) exclude_sites: Vec<Site>,
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Vec::is_empty")]
+    #[param(explode = false)]
+    pub(crate) exclude_languages: Vec<Language>,
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Vec::is_empty")]
+    #[param(explode = false)]
+    pub(crate) exclude_difficulties: Vec<Difficulty>,
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Vec::is_empty")]
+    #[param(explode = false)]
+    pub(crate) exclude_categories: Vec<Category>,
 }
 
-#[derive(Debug, Clone)]
-enum PeriodSelectMode {
+impl SearchQuery {
+    // The query string for this exact search, with settings left at their defaults omitted.
+    pub(crate) fn to_query_string(&self) -> String {
+        serde_urlencoded::to_string(self).expect("failed to encode query")
+    }
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+fn code_schema(codes: &[&str]) -> RefOr<Schema> {
+    ObjectBuilder::new()
+        .schema_type(SchemaType::String)
+        .enum_values(Some(codes.iter().copied()))
+        .into()
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SortOrder {
+    #[default]
+    Code,
+    Name,
+    Points,
+}
+
+impl SortOrder {
+    fn column(self) -> &'static str {
+        match self {
+            Self::Code => "code",
+            Self::Name => "name_se",
+            Self::Points => "points",
+        }
+    }
+
+    fn key(self, course: &Course) -> String {
+        match self {
+            Self::Code => course.code.clone(),
+            Self::Name => course.name_se.clone(),
+            Self::Points => course.points.to_string(),
+        }
+    }
+
+    fn code(self) -> &'static str {
+        match self {
+            Self::Code => "code",
+            Self::Name => "name",
+            Self::Points => "points",
+        }
+    }
+
+    // Keys come from user supplied cursors and must be sanitized like any other input.
+    fn literal(self, key: &str) -> Option<String> {
+        match self {
+            Self::Code | Self::Name => Some(format!("'{}'", key.replace('\'', "''"))),
+            Self::Points => key
+                .parse::<f32>()
+                .ok()
+                .filter(|p| p.is_finite())
+                .map(|p| format!("{}::REAL", p)),
+        }
+    }
+}
+
+impl Serialize for SortOrder {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'s> ToSchema<'s> for SortOrder {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        ("SortOrder", code_schema(&["code", "name", "points"]))
+    }
+}
+
+impl<'de> Deserialize<'de> for SortOrder {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        match String::deserialize(deserializer)?.as_str() {
+            "code" => Ok(Self::Code),
+            "name" => Ok(Self::Name),
+            "points" => Ok(Self::Points),
+            other => Err(DeError::unknown_variant(other, &["code", "name", "points"]))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Next,
+    Previous,
+}
+
+// Points at the row just before (or after) the page it leads to, by sort key and id.
+// It's hex encoded to keep clients from relying on its contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Cursor {
+    direction: Direction,
+    id: i32,
+    key: String,
+}
+
+impl Cursor {
+    pub(crate) fn decode(s: &str) -> Option<Self> {
+        let bytes = (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<_>>>()?;
+        let raw = String::from_utf8(bytes).ok()?;
+
+        let (direction, rest) = raw.split_first_char()?;
+        let direction = match direction {
+            'n' => Direction::Next,
+            'p' => Direction::Previous,
+            _ => return None
+        };
+        let (id, key) = rest.split_once(':')?;
+
+        Some(Self {
+            direction,
+            id: id.parse().ok()?,
+            key: key.to_owned(),
+        })
+    }
+}
+
+impl Display for Cursor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let direction = match self.direction {
+            Direction::Next => 'n',
+            Direction::Previous => 'p',
+        };
+
+        format!("{}{}:{}", direction, self.id, self.key)
+            .bytes()
+            .try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+impl Serialize for Cursor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cursor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        Self::decode(&String::deserialize(deserializer)?).ok_or_else(|| DeError::custom("invalid cursor"))
+    }
+}
+
+// The number of results on each page when `per_page` are asked for.
+pub(crate) fn page_size(per_page: Option<u32>) -> u32 {
+    per_page.unwrap_or(ITEMS_PER_PAGE).clamp(1, MAX_ITEMS_PER_PAGE)
+}
+
+pub(crate) struct Pagination {
+    sort: SortOrder,
+    cursor: Option<Cursor>,
+    pub(crate) per_page: u32,
+    keyset: Option<String>,
+}
+
+impl Pagination {
+    fn new(sort: SortOrder, cursor: Option<Cursor>, per_page: Option<u32>) -> Result<Self, QueryError> {
+        let keyset = match &cursor {
+            Some(cursor) => Some(format!(
+                "({}, id) {} ({}, {})",
+                sort.column(),
+                match cursor.direction {
+                    Direction::Next => ">",
+                    Direction::Previous => "<",
+                },
+                sort.literal(&cursor.key).ok_or(QueryError::Cursor)?,
+                cursor.id
+            )),
+            None => None
+        };
+
+        Ok(Self {
+            sort,
+            cursor,
+            per_page: page_size(per_page),
+            keyset,
+        })
+    }
+
+    fn backwards(&self) -> bool {
+        self.cursor
+            .as_ref()
+            .map(|c| c.direction == Direction::Previous)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Term {
+    Autumn,
+    Spring,
+}
+
+impl Term {
+    pub(crate) fn periods(self) -> RangeInclusive<u8> {
+        match self {
+            Self::Autumn => 1..=2,
+            Self::Spring => 3..=4,
+        }
+    }
+
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            Self::Autumn => "ht",
+            Self::Spring => "vt",
+        }
+    }
+}
+
+impl Serialize for Term {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'s> ToSchema<'s> for Term {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        ("Term", code_schema(&["ht", "vt"]))
+    }
+}
+
+impl<'de> Deserialize<'de> for Term {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        match String::deserialize(deserializer)?.as_str() {
+            "ht" => Ok(Self::Autumn),
+            "vt" => Ok(Self::Spring),
+            other => Err(DeError::unknown_variant(other, &["ht", "vt"]))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PeriodSelectMode {
     Only,
     Starts,
     Ends,
     Spans,
+    Overlaps,
+}
+
+impl PeriodSelectMode {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Only => "only",
+            Self::Starts => "starts",
+            Self::Ends => "ends",
+            Self::Spans => "spans",
+            Self::Overlaps => "overlaps",
+        }
+    }
+}
+
+impl Serialize for PeriodSelectMode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'s> ToSchema<'s> for PeriodSelectMode {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        ("PeriodSelectMode", code_schema(&["only", "starts", "ends", "spans", "overlaps"]))
+    }
 }
 
 impl<'de> Deserialize<'de> for PeriodSelectMode {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
-        match Deserialize::deserialize(deserializer)? {
+        match String::deserialize(deserializer)?.as_str() {
             "only" => Ok(Self::Only),
             "starts" => Ok(Self::Starts),
             "ends" => Ok(Self::Ends),
             "spans" => Ok(Self::Spans),
-            other => Err(DeError::unknown_variant(other, &["only", "starts", "ends", "spans"]))
+            "overlaps" => Ok(Self::Overlaps),
+            other => Err(DeError::unknown_variant(other, &["only", "starts", "ends", "spans", "overlaps"]))
         }
     }
 }
@@ -71,18 +419,42 @@ impl Default for PeriodSelectMode {
     }
 }
 
-#[derive(Debug, Clone)]
-enum CategorySelectMode {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum CategorySelectMode {
     Any,
     All,
+    None,
+}
+
+impl CategorySelectMode {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Any => "any",
+            Self::All => "all",
+            Self::None => "none",
+        }
+    }
+}
+
+impl Serialize for CategorySelectMode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'s> ToSchema<'s> for CategorySelectMode {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        ("CategorySelectMode", code_schema(&["any", "all", "none"]))
+    }
 }
 
 impl<'de> Deserialize<'de> for CategorySelectMode {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
-        match Deserialize::deserialize(deserializer)? {
+        match String::deserialize(deserializer)?.as_str() {
             "any" => Ok(Self::Any),
             "all" => Ok(Self::All),
-            other => Err(DeError::unknown_variant(other, &["any", "all"]))
+            "none" => Ok(Self::None),
+            other => Err(DeError::unknown_variant(other, &["any", "all", "none"]))
         }
     }
 }
@@ -93,181 +465,669 @@ impl Default for CategorySelectMode {
     }
 }
 
-async unsafe fn format_conditions(query: SearchQuery) -> Result<String, ()> {
+// Points are given with at most one decimal, so comparing whole tenths avoids depending on float formatting.
+fn tenths(points: f32) -> i32 {
+    (points * 10.).round() as i32
+}
+
+// Ranges where `from` is after `to` wrap around the end of the year, just like courses do.
+fn selected_periods(periods: Vec<u8>, from: Option<u8>, to: Option<u8>, term: Option<Term>) -> Option<BTreeSet<u8>> {
+    if !periods.iter().chain(&from).chain(&to).all(|p| (1..=4).contains(p)) {
+        return None
+    }
+
+    let range = match (from, to) {
+        (None, None) => Vec::new(),
+        (from, to) => {
+            let (from, to) = (from.unwrap_or(1), to.unwrap_or(4));
+            if from <= to {
+                (from..=to).collect()
+            } else {
+                (from..=4).chain(1..=to).collect()
+            }
+        }
+    };
+
+    Some(periods
+        .into_iter()
+        .chain(range)
+        .chain(term.into_iter().flat_map(Term::periods))
+        .collect())
+}
+
+fn spans(period: u8) -> String {
+    // Courses which wrap over the summer into the next academic year have `period_start > period_end`.
+    format!(
+        "CASE
+        WHEN period_start <= period_end THEN ({} >= period_start AND {} <= period_end)
+        ELSE ({} >= period_start OR {} <= period_end)
+        END",
+        period, period, period, period
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Facet {
+    Site,
+    Language,
+    Difficulty,
+    Pace,
+    Category,
+}
+
+#[derive(Clone)]
+pub(crate) struct Conditions(Vec<(Option<Facet>, String)>);
+
+impl Conditions {
+    // Facet counts are computed as if that facet's own filter was removed.
+    fn to_sql(&self, ignored: Option<Facet>) -> String {
+        let conditions = self.0
+            .iter()
+            .filter(|(facet, _)| ignored.is_none() || *facet != ignored)
+            .map(|(_, condition)| condition)
+            .join(" AND ");
+
+        if conditions.is_empty() {
+            conditions
+        } else {
+            format!("WHERE {}", conditions)
+        }
+    }
+}
+
+fn facet_query(column: &str, from: &str, conditions: &Conditions, facet: Facet) -> String {
+    format!(
+        "SELECT {}, COUNT(*) FROM {} {} GROUP BY 1 ORDER BY 2 DESC",
+        column,
+        from,
+        conditions.to_sql(Some(facet))
+    )
+}
+
+fn present<T>(counts: Vec<(Option<T>, i64)>) -> Vec<(T, i64)> {
+    counts
+        .into_iter()
+        .filter_map(|(value, count)| Some((value?, count)))
+        .collect()
+}
+
+fn counts<S, T>(counts: &[(T, i64)], serializer: S) -> Result<S::Ok, S::Error> where S: Serializer, T: Serialize {
+    serializer.collect_map(counts.iter().map(|(value, count)| (value, count)))
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct Facets {
+    #[serde(serialize_with = "counts")]
+    #[schema(value_type = HashMap<String, i64>)]
+    sites: Vec<(Site, i64)>,
+    #[serde(serialize_with = "counts")]
+    #[schema(value_type = HashMap<String, i64>)]
+    languages: Vec<(Language, i64)>,
+    #[serde(serialize_with = "counts")]
+    #[schema(value_type = HashMap<String, i64>)]
+    difficulties: Vec<(Difficulty, i64)>,
+    #[serde(serialize_with = "counts")]
+    #[schema(value_type = HashMap<String, i64>)]
+    paces: Vec<(Pace, i64)>,
+    #[serde(serialize_with = "counts")]
+    #[schema(value_type = HashMap<String, i64>)]
+    categories: Vec<(Category, i64)>,
+}
+
+#[derive(FromRow)]
+struct SearchRow {
+    id: i32,
+    #[sqlx(flatten)]
+    course: Course,
+}
+
+pub(crate) struct SearchResults {
+    pub(crate) courses: Vec<Course>,
+    pub(crate) total: i64,
+    pub(crate) facets: Facets,
+    pub(crate) next: Option<Cursor>,
+    pub(crate) previous: Option<Cursor>,
+}
+
+#[derive(Template)]
+#[template(path = "../html/search.html")]
+pub(crate) struct SearchTemplate {
+    locale: Locale,
+    switch_language: String,
+    courses: Vec<Course>,
+    total: i64,
+    pages: i64,
+    facets: Facets,
+    next: Option<String>,
+    previous: Option<String>,
+    // Where to post the search to save it.
+    pub(crate) save: Option<String>,
+    // When a saved search was saved.
+    pub(crate) saved: Option<String>,
+}
+
+impl SearchTemplate {
+    // `query` is what the links to other pages are built from.
+    pub(crate) fn new(results: SearchResults, per_page: u32, query: &SearchQuery, locale: Locale, request: &HttpRequest) -> Self {
+        Self {
+            locale,
+            switch_language: locale.switch_link(request),
+            pages: (results.total + per_page as i64 - 1) / per_page as i64,
+            courses: results.courses,
+            total: results.total,
+            facets: results.facets,
+            next: results.next.map(|c| with_cursor(query, c)),
+            previous: results.previous.map(|c| with_cursor(query, c)),
+            save: None,
+            saved: None,
+        }
+    }
+}
+
+pub(crate) fn with_cursor(query: &SearchQuery, cursor: Cursor) -> String {
+    format!("?{}", SearchQuery { cursor: Some(cursor), ..query.clone() }.to_query_string())
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum QueryError {
+    Syntax(ParseError),
+    CodePattern(String, &'static str),
+    Periods,
+    PacePercentage,
+    Cursor,
+    // The search can't be written in the query language the way it is, so it can't be saved.
+    Unsaveable,
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Syntax(e) => write!(f, "invalid query {}", e),
+            Self::CodePattern(pattern, reason) => write!(f, "invalid course code pattern \"{}\": {}", pattern, reason),
+            Self::Periods => write!(f, "periods must be between 1 and 4"),
+            Self::PacePercentage => write!(f, "pace percentages must be between 0 and 100"),
+            Self::Cursor => write!(f, "the cursor does not match the sort order"),
+            Self::Unsaveable => write!(f, "the search can't be saved as it is written"),
+        }
+    }
+}
+
+impl QueryError {
+    // The query string parameters that could be at fault, where everything but the cursor comes from `q` when it is given.
+    pub(crate) fn parameters(&self, text: bool) -> &'static [&'static str] {
+        match self {
+            Self::Cursor => &["cursor"],
+            Self::Syntax(_) => &["q"],
+            _ if text => &["q"],
+            Self::CodePattern(..) => &["code_pattern"],
+            Self::Periods => &["periods", "period_from", "period_to", "term"],
+            Self::PacePercentage => &["pace_min", "pace_max"],
+            Self::Unsaveable => &[],
+        }
+    }
+}
+
+// Translates a glob style pattern, where `?` matches one character and `*` any number of them, into a LIKE pattern.
+// Patterns without `*` that are shorter than a full code match codes starting with them.
+fn translate_code_pattern(pattern: &str) -> Result<String, QueryError> {
+    let error = |reason| QueryError::CodePattern(pattern.to_owned(), reason);
+    let upper = pattern.trim().to_uppercase();
+
+    if upper.is_empty() {
+        return Err(error("the pattern is empty"))
+    }
+    if !upper.chars().all(|c| c.is_ascii_alphanumeric() || c == '?' || c == '*') {
+        return Err(error("only letters, digits, ? and * are allowed"))
+    }
+    if upper.chars().filter(|&c| c != '*').count() > 6 {
+        return Err(error("course codes are six characters long"))
+    }
+
+    let mut translated = upper
+        .chars()
+        .map(|c| match c {
+            '?' => '_',
+            '*' => '%',
+            c => c,
+        })
+        .collect::<String>();
+    if !upper.contains('*') && upper.len() < 6 {
+        translated.push('%');
+    }
+
+    Ok(translated)
+}
+
+async unsafe fn format_conditions(query: SearchQuery) -> Result<Conditions, QueryError> {
     // Using string concatenation instead of binding values makes this code much more manageable.
     // STRING INPUTS MUST BE SANITIZED MANUALLY!
+    // Enum values are written as their database labels, which are the variant names, and not with `Display`.
 
     let SearchQuery {
-        page: _,
+        cursor: _,
+        per_page: _,
+        sort: _,
         code_pattern,
         mut name_pattern,
+        points_min,
+        points_max,
         points,
+        credit_units,
         paces,
-        period,
+        pace_times,
+        pace_min,
+        pace_max,
+        periods,
+        period_from,
+        period_to,
+        term,
         period_select_mode,
         sites,
         languages,
         difficulties,
         categories,
         category_select_mode,
+        exclude_paces,
+        exclude_sites,
+        exclude_languages,
+        exclude_difficulties,
+        exclude_categories,
     } = query;
 
-    if code_pattern.as_deref().map(|p| !CODE_PAT_VALIDATE.is_match(p)).unwrap_or_default() {
-        return Err(())
+    let code_pattern = code_pattern.as_deref().map(translate_code_pattern).transpose()?;
+    let periods = selected_periods(periods, period_from, period_to, term).ok_or(QueryError::Periods)?;
+    if pace_min.iter().chain(&pace_max).any(|&p| p > 100) {
+        return Err(QueryError::PacePercentage)
     }
 
     name_pattern = name_pattern.map(|p| SPECIAL_CHARACTERS.replace_all(&p, "").into());
 
-    let conditions = vec![
-        code_pattern.map(|p| format!("code ILIKE {}", p)),
-        points.map(|p| format!("points = {}", p)),
-        paces.map(|p|
-            format!("pace in ({})", p
+    let conditions = [
+        (None, code_pattern.map(|p| format!("code LIKE '{}'", p))),
+        (None, points_min.map(|p| format!("ROUND(points * 10) >= {}", tenths(p)))),
+        (None, points_max.map(|p| format!("ROUND(points * 10) <= {}", tenths(p)))),
+        (None, Some(points)
+            .filter(|v| !v.is_empty())
+            .map(|v| format!("ROUND(points * 10) in ({})", v
                 .into_iter()
-                .map(|s| format!("'{}'", s))
+                .map(tenths)
                 .join(",")
-            )),
-        period.map(|p| match period_select_mode {
-            PeriodSelectMode::Only => format!("period_start = {} AND period_end = {}", p, p),
-            PeriodSelectMode::Starts => format!("period_start = {}", p),
-            PeriodSelectMode::Ends => format!("period_end = {}", p),
-            PeriodSelectMode::Spans => format!(
-                "CASE
-                WHEN period_start < period_end THEN ({} >= period_start AND {} <= period_end)
-                ELSE ({} >= period_start OR {} <= period_end)
-                END",
-                p, p, p, p
-            )
-        }),
-        Some(sites)
+            ))),
+        (None, Some(credit_units)
+            .filter(|v| !v.is_empty())
+            .map(|v| format!("credit_unit in ({})", v
+                .into_iter()
+                .map(|s| format!("'{:?}'", s))
+                .join(",")
+            ))),
+        // Paces are compared as text, since the enum only has labels for the paces courses have been given so far.
+        (Some(Facet::Pace), paces
+            .filter(|v| !v.is_empty())
+            .map(|v| format!("pace::TEXT in ({})", v
+                .into_iter()
+                .map(|s| format!("'{}'", s.label()))
+                .join(",")
+            ))),
+        (Some(Facet::Pace), Some(pace_times)
+            .filter(|v| !v.is_empty())
+            .map(|v| format!("({})", v
+                .into_iter()
+                .map(|t| format!("pace::TEXT LIKE '{:?}%'", t))
+                .join(" OR ")
+            ))),
+        (Some(Facet::Pace), pace_min.map(|p| format!(r"SUBSTRING(pace::TEXT FROM '\d+')::INT >= {}", p))),
+        (Some(Facet::Pace), pace_max.map(|p| format!(r"SUBSTRING(pace::TEXT FROM '\d+')::INT <= {}", p))),
+        (None, Some(periods)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                let list = s.iter().join(",");
+                match period_select_mode {
+                    PeriodSelectMode::Only => once(format!("period_start in ({}) AND period_end in ({})", list, list))
+                        .chain((1..=4)
+                            .filter(|p| !s.contains(p))
+                            .map(|p| format!("NOT ({})", spans(p))))
+                        .join(" AND "),
+                    PeriodSelectMode::Starts => format!("period_start in ({})", list),
+                    PeriodSelectMode::Ends => format!("period_end in ({})", list),
+                    PeriodSelectMode::Spans => s
+                        .iter()
+                        .map(|&p| spans(p))
+                        .join(" AND "),
+                    PeriodSelectMode::Overlaps => format!("({})", s
+                        .iter()
+                        .map(|&p| spans(p))
+                        .join(" OR ")
+                    ),
+                }
+            })),
+        (Some(Facet::Site), Some(sites)
             .filter(|v| !v.is_empty())
             .map(|v| format!("site in ({})", v
                 .into_iter()
-                .map(|s| format!("'{}'", s))
+                .map(|s| format!("'{:?}'", s))
                 .join(",")
-            )),
-        Some(languages)
+            ))),
+        (Some(Facet::Language), Some(languages)
             .filter(|v| !v.is_empty())
             .map(|v| format!("language in ({})", v
                 .into_iter()
-                .map(|s| format!("'{}'", s))
+                .map(|s| format!("'{:?}'", s))
                 .join(",")
-            )),
-        Some(difficulties)
+            ))),
+        (Some(Facet::Difficulty), Some(difficulties)
             .filter(|v| !v.is_empty())
             .map(|v| format!("difficulty in ({})", v
                 .into_iter()
-                .map(|s| format!("'{}'", s))
+                .map(|s| format!("'{:?}'", s))
                 .join(",")
-            )),
-        Some(categories)
+            ))),
+        (Some(Facet::Category), Some(categories)
             .filter(|v| !v.is_empty())
-            .map(|v| format!("ARRAY[{}]::category_enum[] {} categories",
-                v
+            .map(|v| {
+                let array = format!("ARRAY[{}]::category_enum[]", v
                     .into_iter()
-                    .map(|s| format!("'{}'", s))
-                    .join(","),
+                    .map(|s| format!("'{:?}'", s))
+                    .join(",")
+                );
                 match category_select_mode {
-                    CategorySelectMode::Any => "&&",
-                    CategorySelectMode::All => "<@"
+                    CategorySelectMode::Any => format!("{} && categories", array),
+                    CategorySelectMode::All => format!("{} <@ categories", array),
+                    CategorySelectMode::None => format!("NOT ({} && categories)", array),
                 }
-            )),
+            })),
+        // Nullable columns need an explicit check since `NULL NOT IN (...)` is never true.
+        (Some(Facet::Pace), Some(exclude_paces)
+            .filter(|v| !v.is_empty())
+            .map(|v| format!("(pace IS NULL OR pace::TEXT NOT IN ({}))", v
+                .into_iter()
+                .map(|s| format!("'{}'", s.label()))
+                .join(",")
+            ))),
+        (Some(Facet::Site), Some(exclude_sites)
+            .filter(|v| !v.is_empty())
+            .map(|v| format!("(site IS NULL OR site NOT IN ({}))", v
+                .into_iter()
+                .map(|s| format!("'{:?}'", s))
+                .join(",")
+            ))),
+        (Some(Facet::Language), Some(exclude_languages)
+            .filter(|v| !v.is_empty())
+            .map(|v| format!("(language IS NULL OR language NOT IN ({}))", v
+                .into_iter()
+                .map(|s| format!("'{:?}'", s))
+                .join(",")
+            ))),
+        (Some(Facet::Difficulty), Some(exclude_difficulties)
+            .filter(|v| !v.is_empty())
+            .map(|v| format!("difficulty NOT IN ({})", v
+                .into_iter()
+                .map(|s| format!("'{:?}'", s))
+                .join(",")
+            ))),
+        (Some(Facet::Category), Some(exclude_categories)
+            .filter(|v| !v.is_empty())
+            .map(|v| format!("NOT (ARRAY[{}]::category_enum[] && categories)", v
+                .into_iter()
+                .map(|s| format!("'{:?}'", s))
+                .join(",")
+            ))),
         // This condition is moved to the end since it is by far the slowest and should therefor not run as often.
-        name_pattern.map(|p| format!(r"REGEXP_REPLACE(name_se, '[^\pL\d\s]', '', 'g') ILIKE %{}% OR REGEXP_REPLACE(name_en, '[^\pL\d\s]', '', 'g') ILIKE %{}%", p, p)),
+        (None, name_pattern.map(|p| format!(r"(REGEXP_REPLACE(name_se, '[^\pL\d\s]', '', 'g') ILIKE '%{}%' OR REGEXP_REPLACE(name_en, '[^\pL\d\s]', '', 'g') ILIKE '%{}%')", p, p))),
     ];
 
-    if conditions.is_empty() {
-        Ok("".into())
-    } else {
-        Ok(format!("WHERE {}", conditions
-            .into_iter()
-            .flatten()
-            .join(" AND ")))
-    }
+    Ok(Conditions(conditions
+        .into_iter()
+        .filter_map(|(facet, condition)| Some((facet, condition?)))
+        .collect()))
 }
 
-#[get("/")]
-pub async fn page(query: web::Query<SearchQuery>) -> impl Responder {
-    let page_number = query.0.page;
-
-    let conditions = if let Ok(conditions) = unsafe { format_conditions(query.0) }.await {
-        conditions
-    } else {
-        // invalid query
-        return HttpResponse::NotFound().finish() // FIXME
-    };
+pub(crate) async fn search(conditions: &Conditions, pagination: &Pagination) -> Result<SearchResults, SqlxError> {
+    let connection = CONNECTION.get().unwrap();
+
+    let mut page_conditions = conditions.clone();
+    page_conditions.0.extend(pagination.keyset.clone().map(|k| (None, k)));
+    let order = if pagination.backwards() { "DESC" } else { "ASC" };
 
-    let res = QueryBuilder::new(format!(
+    let rows = format!(
         // TODO: what columns are needed?
-        r#"SELECT code, name_se, name_en, url, points, pace, prerequisites, register_info, modules, period_start, period_end, site as "site: _", language as "language: _", difficulty as "difficulty: _", categories as "categories: _", conduct
+        "SELECT id, code, name_se, name_en, url, points, credit_unit, pace, prerequisites, register_info, modules, period_start, period_end, start_date, end_date, site, language, difficulty, categories, conduct
         FROM courses
         {}
-        ORDER BY id
-        OFFSET {}
-        LIMIT {}"#,
-        conditions,
-        page_number * ITEMS_PER_PAGE,
-        ITEMS_PER_PAGE
-    ))
-        // TODO: remove type annotation
-        .build_query_as::<Course>()
-        .fetch_all(CONNECTION.get().unwrap())
-        .await;
-
-    match res {
-        Ok(courses) => {
-            /*let courses = stream::iter(courses)
-                .filter_map(|c| async move {
-                    /*let SearchQuery {
-                        page,
-                        code_pattern,
-                        name_pattern,
-                        points,
-                        paces,
-                        period,
-                        period_select_mode,
-                        sites,
-                        language,
-                        difficulties,
-                        categories,
-                        category_select_mode,
-                    } = (&query.0).clone();*/
-
-                    let Course {
-                        code,
-                        name_se,
-                        name_en,
-                        points,
-                        pace,
-                        period_start,
-                        period_end,
-                        site,
-                        language,
-                        difficulty,
-                        categories,
-                        ..
-                    } = c;
-
-                    Some(OptionFuture::from((&query).code_pattern
-                        .as_ref()
-                        .map(|pat| match_code_pattern(pat, code)))
-                        .await
-                        .unwrap_or(true)
-                    && OptionFuture::from((&query).name_pattern
-                        .as_ref()
-                        .map(|pat| match_name_pattern(pat, name_se, name_en)))
-                        .await
-                        .unwrap_or(true))
-                })
-                .skip(query.page as usize * ITEMS_PER_PAGE)
-                .take(ITEMS_PER_PAGE);*/
-
-            HttpResponse::Ok()
-                .body(todo!())
+        ORDER BY {} {}, id {}
+        LIMIT {}",
+        page_conditions.to_sql(None),
+        pagination.sort.column(),
+        order,
+        order,
+        // One extra row tells whether there is another page.
+        pagination.per_page + 1
+    );
+    let total = format!("SELECT COUNT(*) FROM courses {}", conditions.to_sql(None));
+    let sites = facet_query("site", "courses", conditions, Facet::Site);
+    let languages = facet_query("language", "courses", conditions, Facet::Language);
+    let difficulties = facet_query("difficulty", "courses", conditions, Facet::Difficulty);
+    let paces = facet_query("pace", "courses", conditions, Facet::Pace);
+    let categories = facet_query("category", "courses CROSS JOIN UNNEST(categories) AS category", conditions, Facet::Category);
+
+    let (mut rows, total, sites, languages, difficulties, paces, categories) = try_join!(
+        query_as::<_, SearchRow>(&rows).fetch_all(connection),
+        query_scalar::<_, i64>(&total).fetch_one(connection),
+        query_as::<_, (Option<Site>, i64)>(&sites).fetch_all(connection),
+        query_as::<_, (Option<Language>, i64)>(&languages).fetch_all(connection),
+        query_as::<_, (Difficulty, i64)>(&difficulties).fetch_all(connection),
+        query_as::<_, (Option<Pace>, i64)>(&paces).fetch_all(connection),
+        query_as::<_, (Category, i64)>(&categories).fetch_all(connection),
+    )?;
+
+    let more = rows.len() > pagination.per_page as usize;
+    rows.truncate(pagination.per_page as usize);
+    if pagination.backwards() {
+        rows.reverse();
+    }
+
+    let cursor = |direction, row: &SearchRow| Cursor {
+        direction,
+        id: row.id,
+        key: pagination.sort.key(&row.course),
+    };
+    let next = rows
+        .last()
+        .filter(|_| more || pagination.backwards())
+        .map(|r| cursor(Direction::Next, r));
+    let previous = rows
+        .first()
+        .filter(|_| if pagination.backwards() { more } else { pagination.cursor.is_some() })
+        .map(|r| cursor(Direction::Previous, r));
+
+    Ok(SearchResults {
+        courses: rows.into_iter().map(|r| r.course).collect(),
+        total,
+        facets: Facets {
+            sites: present(sites),
+            languages: present(languages),
+            difficulties,
+            paces: present(paces),
+            categories,
+        },
+        next,
+        previous,
+    })
+}
+
+// Searches written in the query language are passed as `q`, and replace any other filters.
+#[derive(Deserialize, IntoParams, Debug, Default, Clone)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct TextQuery {
+    /// A search in the query language, such as `site:lu lang:en points>=7.5 period:2-3 cat:dat "machine learning"`.
+    #[serde(default)]
+    pub(crate) q: Option<String>,
+}
+
+pub(crate) fn merge(query: SearchQuery, text: TextQuery) -> Result<SearchQuery, QueryError> {
+    match text.q {
+        Some(q) => Ok(SearchQuery {
+            cursor: query.cursor,
+            per_page: query.per_page,
+            ..q.parse().map_err(QueryError::Syntax)?
+        }),
+        None => Ok(query)
+    }
+}
+
+pub(crate) async fn prepare(query: SearchQuery, text: TextQuery) -> Result<(Conditions, Pagination), QueryError> {
+    let query = merge(query, text)?;
+    let pagination = Pagination::new(query.sort, query.cursor.clone(), query.per_page)?;
+    let conditions = unsafe { format_conditions(query) }.await?;
+
+    Ok((conditions, pagination))
+}
+
+#[get("/")]
+pub async fn page(locale: Locale, format: Format, request: HttpRequest) -> Result<HttpResponse, PageError> {
+    let query = parse_query::<SearchQuery>(locale, request.query_string())?;
+    let text = parse_query::<TextQuery>(locale, request.query_string())?;
+    let with_text = text.q.is_some();
+    let invalid = |e| PageError::invalid_query(locale, e, with_text);
+
+    let query = merge(query, text).map_err(invalid)?;
+
+    let changed = changed();
+    let (conditions, pagination) = prepare(query.clone(), TextQuery::default()).await.map_err(invalid)?;
+    if let Some(response) = not_modified(&request) {
+        return Ok(response)
+    }
+    let results = search(&conditions, &pagination).await.map_err(|e| PageError::internal(locale, e))?;
+
+    let body = match format {
+        Format::Html => SearchTemplate {
+            save: Some(format!("/saved?{}", SearchQuery { cursor: None, ..query.clone() }.to_query_string())),
+            ..SearchTemplate::new(results, pagination.per_page, &query, locale, &request)
+        }.render().unwrap(),
+        Format::Json => serde_json::to_string(&SearchResponse::from(results)).unwrap(),
+        Format::Markdown => search_markdown(&results, &query, locale),
+        Format::Csv => csv(&results.courses),
+    };
+
+    Ok(Page::new(format, body, changed).respond(&request))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::web::Query;
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    fn round_trip(query: SearchQuery) {
+        let encoded = query.to_query_string();
+        let decoded = Query::<SearchQuery>::from_query(&encoded)
+            .unwrap_or_else(|e| panic!("failed to decode `{}`: {}", encoded, e))
+            .into_inner();
+        assert_eq!(decoded, query, "`{}` decoded differently", encoded);
+    }
+
+    #[test]
+    fn default_is_empty() {
+        assert_eq!(SearchQuery::default().to_query_string(), "");
+        round_trip(SearchQuery::default());
+    }
+
+    #[test]
+    fn every_variant_round_trips() {
+        let paces = Time::iter()
+            .flat_map(|t| [0, 25, 50, 100].map(|p| Pace::new(t, p.try_into().unwrap())))
+            .collect::<Vec<_>>();
+
+        round_trip(SearchQuery {
+            credit_units: CreditUnit::iter().collect(),
+            paces: Some(paces.clone()),
+            pace_times: Time::iter().collect(),
+            sites: Site::iter().collect(),
+            languages: Language::iter().collect(),
+            difficulties: Difficulty::iter().collect(),
+            categories: Category::iter().collect(),
+            ..Default::default()
+        });
+        round_trip(SearchQuery {
+            exclude_paces: paces,
+            exclude_sites: Site::iter().collect(),
+            exclude_languages: Language::iter().collect(),
+            exclude_difficulties: Difficulty::iter().collect(),
+            exclude_categories: Category::iter().collect(),
+            ..Default::default()
+        });
+
+        for sort in [SortOrder::Code, SortOrder::Name, SortOrder::Points] {
+            round_trip(SearchQuery { sort, ..Default::default() });
         }
-        Err(e) => {
-            eprintln!("{}", e);
-            HttpResponse::InternalServerError().finish() // FIXME
+        for term in [Term::Autumn, Term::Spring] {
+            round_trip(SearchQuery { term: Some(term), ..Default::default() });
         }
+        for period_select_mode in [PeriodSelectMode::Only, PeriodSelectMode::Starts, PeriodSelectMode::Ends, PeriodSelectMode::Spans, PeriodSelectMode::Overlaps] {
+            round_trip(SearchQuery { period_select_mode, ..Default::default() });
+        }
+        for category_select_mode in [CategorySelectMode::Any, CategorySelectMode::All, CategorySelectMode::None] {
+            round_trip(SearchQuery { category_select_mode, ..Default::default() });
+        }
+    }
+
+    #[test]
+    fn other_fields_round_trip() {
+        round_trip(SearchQuery {
+            cursor: Some(Cursor { direction: Direction::Previous, id: 12, key: "Å, b & c=d".to_owned() }),
+            per_page: Some(50),
+            code_pattern: Some("d00*".to_owned()),
+            name_pattern: Some("machine learning, 2".to_owned()),
+            points_min: Some(1.5),
+            points_max: Some(30.),
+            points: vec![7.5, 15., 0.5],
+            pace_min: Some(25),
+            pace_max: Some(100),
+            periods: vec![1, 3],
+            period_from: Some(4),
+            period_to: Some(2),
+            ..Default::default()
+        });
+    }
+
+    fn conditions(query: SearchQuery) -> String {
+        let conditions = actix_web::rt::System::new().block_on(unsafe { format_conditions(query) }).unwrap();
+        conditions.to_sql(None)
+    }
+
+    #[test]
+    fn empty_lists_filter_nothing() {
+        assert_eq!(conditions(SearchQuery { paces: Some(Vec::new()), ..Default::default() }), "");
+
+        let query = Query::<SearchQuery>::from_query("paces=&sites=&points=").unwrap().into_inner();
+        assert_eq!(conditions(query), "");
+    }
+
+    #[test]
+    fn pace_conditions() {
+        let query = SearchQuery {
+            paces: Some(vec![Pace::new(Time::Day, 50.try_into().unwrap())]),
+            exclude_paces: vec![Pace::new(Time::Weekend, 25.try_into().unwrap())],
+            ..Default::default()
+        };
+        assert_eq!(conditions(query), "WHERE pace::TEXT in ('Day50') AND (pace IS NULL OR pace::TEXT NOT IN ('Weekend25'))");
+    }
+
+    #[test]
+    fn paces_without_labels() {
+        // Neither is a label of `pace_enum`, so casting them to it would fail.
+        let query = Query::<SearchQuery>::from_query("paces=d42&exclude_paces=w50").unwrap().into_inner();
+        assert_eq!(conditions(query), "WHERE pace::TEXT in ('Day42') AND (pace IS NULL OR pace::TEXT NOT IN ('Weekend50'))");
+    }
+
+    #[test]
+    fn encoding_is_canonical() {
+        let query = Query::<SearchQuery>::from_query("sites=lu,,pt&per_page=20&sort=code&category_select_mode=any&periods=2")
+            .unwrap()
+            .into_inner();
+        assert_eq!(query.to_query_string(), "per_page=20&periods=2&sites=lu%2Cpt");
+
+        let empty = Query::<SearchQuery>::from_query("paces=&sites=").unwrap().into_inner();
+        assert_eq!(empty, SearchQuery::default());
     }
 }
@@ -0,0 +1,180 @@
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{Error as DeError, Unexpected, Visitor};
+use serde::forward_to_deserialize_any;
+use serde::ser::{Error as SerError, Impossible};
+
+// Query strings can't hold sequences, so lists are written as comma separated values, like `sites=lu,pt`.
+// Used with `#[serde(with = "comma_separated")]`, or `comma_separated::optional` for optional lists, where an empty one counts as none.
+
+pub fn serialize<S, T>(values: &[T], serializer: S) -> Result<S::Ok, S::Error> where S: Serializer, T: Serialize {
+    let items = values
+        .iter()
+        .map(|v| v.serialize(ItemSerializer))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(S::Error::custom)?;
+
+    serializer.serialize_str(&items.join(","))
+}
+
+pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error> where D: Deserializer<'de>, T: Deserialize<'de> {
+    deserializer.deserialize_str(ListVisitor(PhantomData))
+}
+
+pub mod optional {
+    use super::*;
+
+    pub fn serialize<S, T>(values: &Option<Vec<T>>, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer, T: Serialize {
+        match values {
+            Some(values) => super::serialize(values, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error> where D: Deserializer<'de>, T: Deserialize<'de> {
+        super::deserialize(deserializer).map(|v: Vec<T>| Some(v).filter(|v| !v.is_empty()))
+    }
+}
+
+struct ListVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for ListVisitor<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("a comma separated list")
+    }
+
+    fn visit_str<E: DeError>(self, v: &str) -> Result<Self::Value, E> {
+        v
+            .split(',')
+            .filter(|item| !item.is_empty())
+            .map(|item| T::deserialize(ItemDeserializer(item, PhantomData)))
+            .collect()
+    }
+}
+
+// Items are plain strings, which are parsed when a number is expected, the same way `serde_urlencoded` treats values.
+struct ItemDeserializer<'a, E>(&'a str, PhantomData<E>);
+
+macro_rules! parse_number {
+    ($($method:ident => $visit:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self.0.parse() {
+                    Ok(number) => visitor.$visit(number),
+                    Err(_) => Err(E::invalid_value(Unexpected::Str(self.0), &visitor)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de, 'a, E: DeError> Deserializer<'de> for ItemDeserializer<'a, E> {
+    type Error = E;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_str(self.0)
+    }
+
+    parse_number!(
+        deserialize_u8 => visit_u8, deserialize_u16 => visit_u16, deserialize_u32 => visit_u32, deserialize_u64 => visit_u64,
+        deserialize_i8 => visit_i8, deserialize_i16 => visit_i16, deserialize_i32 => visit_i32, deserialize_i64 => visit_i64,
+        deserialize_f32 => visit_f32, deserialize_f64 => visit_f64
+    );
+
+    forward_to_deserialize_any! {
+        bool i128 u128 char str string bytes byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+#[derive(Debug)]
+struct ItemError(String);
+
+impl Display for ItemError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ItemError {}
+
+impl SerError for ItemError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+// Turns a single value into its text, which only works for values that are plain strings or numbers.
+struct ItemSerializer;
+
+macro_rules! write_display {
+    ($($method:ident: $ty:ty),*) => {
+        $(
+            fn $method(self, v: $ty) -> Result<Self::Ok, Self::Error> {
+                Ok(v.to_string())
+            }
+        )*
+    };
+}
+
+macro_rules! unsupported {
+    ($($method:ident($($arg:ty),*) -> $ok:ty),*) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<$ok, Self::Error> {
+                Err(ItemError::custom("list items must be strings or numbers"))
+            }
+        )*
+    };
+}
+
+impl Serializer for ItemSerializer {
+    type Ok = String;
+    type Error = ItemError;
+    type SerializeSeq = Impossible<String, ItemError>;
+    type SerializeTuple = Impossible<String, ItemError>;
+    type SerializeTupleStruct = Impossible<String, ItemError>;
+    type SerializeTupleVariant = Impossible<String, ItemError>;
+    type SerializeMap = Impossible<String, ItemError>;
+    type SerializeStruct = Impossible<String, ItemError>;
+    type SerializeStructVariant = Impossible<String, ItemError>;
+
+    write_display!(
+        serialize_bool: bool, serialize_char: char, serialize_str: &str,
+        serialize_u8: u8, serialize_u16: u16, serialize_u32: u32, serialize_u64: u64,
+        serialize_i8: i8, serialize_i16: i16, serialize_i32: i32, serialize_i64: i64,
+        serialize_f32: f32, serialize_f64: f64
+    );
+
+    fn serialize_unit_variant(self, _: &'static str, _: u32, variant: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(variant.to_owned())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _: &'static str, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(self, _: &'static str, _: u32, _: &'static str, _: &T) -> Result<Self::Ok, Self::Error> {
+        Err(ItemError::custom("list items must be strings or numbers"))
+    }
+
+    unsupported!(
+        serialize_bytes(&[u8]) -> String,
+        serialize_none() -> String,
+        serialize_unit() -> String,
+        serialize_unit_struct(&'static str) -> String,
+        serialize_seq(Option<usize>) -> Self::SerializeSeq,
+        serialize_tuple(usize) -> Self::SerializeTuple,
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct,
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant,
+        serialize_map(Option<usize>) -> Self::SerializeMap,
+        serialize_struct(&'static str, usize) -> Self::SerializeStruct,
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant
+    );
+}
@@ -0,0 +1,345 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use serde::de::value::{BorrowedStrDeserializer, Error as ValueError};
+
+use super::SearchQuery;
+
+// A compact way of writing a search in a single text box, such as
+// `site:lu lang:en points>=7.5 period:2-3 cat:dat "machine learning"`.
+// Values use the same short codes as the query string, and any other words are matched against course names.
+
+lazy_static! {
+    static ref FILTER_PAT: Regex = Regex::new(r"^(-?)([a-z]+)(:|=|>=|<=)(.*)$").expect("failed to parse regex");
+}
+
+const FILTERS: [&str; 14] = ["code", "points", "unit", "pace", "time", "period", "term", "pmode", "site", "lang", "diff", "cat", "cmode", "sort"];
+const NEGATABLE: [&str; 5] = ["pace", "site", "lang", "diff", "cat"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    position: usize,
+    message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "at position {}: {}", self.position, self.message)
+    }
+}
+
+struct Token<'a> {
+    start: usize,
+    text: &'a str,
+    quoted: bool,
+}
+
+struct Parser<'a> {
+    input: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    // Positions are reported as 1-based character columns, while everything internal uses byte offsets.
+    fn error(&self, at: usize, message: impl Into<String>) -> ParseError {
+        ParseError {
+            position: self.input[..at].chars().count() + 1,
+            message: message.into(),
+        }
+    }
+
+    fn tokenize(&self) -> Result<Vec<Token<'a>>, ParseError> {
+        let mut tokens = Vec::new();
+        let mut chars = self.input.char_indices().peekable();
+
+        while let Some(&(start, c)) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c == '"' {
+                chars.next();
+                let end = chars
+                    .by_ref()
+                    .find(|&(_, c)| c == '"')
+                    .map(|(i, _)| i)
+                    .ok_or_else(|| self.error(start, "unterminated quote"))?;
+                tokens.push(Token { start: start + 1, text: &self.input[start + 1..end], quoted: true });
+            } else {
+                let end = chars
+                    .by_ref()
+                    .find(|(_, c)| c.is_whitespace())
+                    .map(|(i, _)| i)
+                    .unwrap_or(self.input.len());
+                tokens.push(Token { start, text: &self.input[start..end], quoted: false });
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    fn value<T: Deserialize<'a>>(&self, value: &'a str, at: usize) -> Result<T, ParseError> {
+        T::deserialize(BorrowedStrDeserializer::<ValueError>::new(value))
+            .map_err(|e| self.error(at, e.to_string()))
+    }
+
+    fn number<T: FromStr>(&self, value: &str, at: usize) -> Result<T, ParseError> {
+        value
+            .parse()
+            .map_err(|_| self.error(at, format!("expected a number, found `{}`", value)))
+    }
+
+    fn period(&self, value: &str, at: usize) -> Result<u8, ParseError> {
+        Some(self.number(value, at)?)
+            .filter(|p| (1..=4).contains(p))
+            .ok_or_else(|| self.error(at, "periods must be between 1 and 4"))
+    }
+
+    // Calls `parse` with every comma separated item and its position, adding the results to `values`.
+    fn list<T>(&self, values: &mut Vec<T>, value: &'a str, at: usize, parse: impl Fn(&Self, &'a str, usize) -> Result<T, ParseError>) -> Result<(), ParseError> {
+        let mut offset = at;
+        for item in value.split(',') {
+            values.push(parse(self, item, offset)?);
+            offset += item.len() + 1;
+        }
+
+        Ok(())
+    }
+
+    fn filter(&self, query: &mut SearchQuery, negated: bool, key: &str, operator: &str, value: &'a str, at: usize) -> Result<(), ParseError> {
+        if !FILTERS.contains(&key) {
+            return Err(self.error(at, format!("unknown filter `{}`, expected one of {}", key, FILTERS.join(", "))))
+        }
+        if negated && !NEGATABLE.contains(&key) {
+            return Err(self.error(at, format!("`{}` can't be negated", key)))
+        }
+        let at = at + negated as usize + key.len() + operator.len();
+        if value.is_empty() {
+            return Err(self.error(at, format!("missing value for `{}`", key)))
+        }
+
+        match (key, operator) {
+            ("code", ":" | "=") => query.code_pattern = Some(value.to_owned()),
+            ("points", ":" | "=") => self.list(&mut query.points, value, at, Self::number)?,
+            ("points", ">=") => query.points_min = Some(self.number(value, at)?),
+            ("points", "<=") => query.points_max = Some(self.number(value, at)?),
+            ("unit", ":" | "=") => self.list(&mut query.credit_units, value, at, Self::value)?,
+            ("pace", ":" | "=") if negated => self.list(&mut query.exclude_paces, value, at, Self::value)?,
+            ("pace", ":" | "=") => self.list(query.paces.get_or_insert_with(Vec::new), value, at, Self::value)?,
+            ("pace", ">=") => query.pace_min = Some(self.number(value, at)?),
+            ("pace", "<=") => query.pace_max = Some(self.number(value, at)?),
+            ("time", ":" | "=") => self.list(&mut query.pace_times, value, at, Self::value)?,
+            ("period", ":" | "=") => {
+                let mut items = Vec::new();
+                self.list(&mut items, value, at, |_, item, item_at| Ok((item, item_at)))?;
+                for (item, item_at) in items {
+                    if let Some((from, to)) = item.split_once('-') {
+                        if query.period_from.is_some() || query.period_to.is_some() {
+                            return Err(self.error(item_at, "only one period range is allowed"))
+                        }
+                        query.period_from = Some(self.period(from, item_at)?);
+                        query.period_to = Some(self.period(to, item_at + from.len() + 1)?);
+                    } else {
+                        query.periods.push(self.period(item, item_at)?);
+                    }
+                }
+            }
+            ("term", ":" | "=") => query.term = Some(self.value(value, at)?),
+            ("pmode", ":" | "=") => query.period_select_mode = self.value(value, at)?,
+            ("site", ":" | "=") if negated => self.list(&mut query.exclude_sites, value, at, Self::value)?,
+            ("site", ":" | "=") => self.list(&mut query.sites, value, at, Self::value)?,
+            ("lang", ":" | "=") if negated => self.list(&mut query.exclude_languages, value, at, Self::value)?,
+            ("lang", ":" | "=") => self.list(&mut query.languages, value, at, Self::value)?,
+            ("diff", ":" | "=") if negated => self.list(&mut query.exclude_difficulties, value, at, Self::value)?,
+            ("diff", ":" | "=") => self.list(&mut query.difficulties, value, at, Self::value)?,
+            ("cat", ":" | "=") if negated => self.list(&mut query.exclude_categories, value, at, Self::value)?,
+            ("cat", ":" | "=") => self.list(&mut query.categories, value, at, Self::value)?,
+            ("cmode", ":" | "=") => query.category_select_mode = self.value(value, at)?,
+            ("sort", ":" | "=") => query.sort = self.value(value, at)?,
+            (key, operator) => return Err(self.error(at - operator.len(), format!("`{}` can't be used with `{}`", key, operator)))
+        }
+
+        Ok(())
+    }
+
+    fn parse(&self) -> Result<SearchQuery, ParseError> {
+        let mut query = SearchQuery::default();
+        let mut words = Vec::new();
+
+        for token in self.tokenize()? {
+            let captures = Some(&token)
+                .filter(|t| !t.quoted)
+                .and_then(|t| FILTER_PAT.captures(t.text));
+
+            if let Some(captures) = captures {
+                let (negated, key, operator, value) = captures
+                    .iter()
+                    .skip(1)
+                    .map(|m| m.map(|m| m.as_str()).unwrap_or_default())
+                    .collect_tuple()
+                    .expect("wrong number of captures for filter");
+                self.filter(&mut query, !negated.is_empty(), key, operator, value, token.start)?;
+            } else {
+                words.push(token.text);
+            }
+        }
+
+        if !words.is_empty() {
+            query.name_pattern = Some(words.join(" "));
+        }
+
+        Ok(query)
+    }
+}
+
+impl FromStr for SearchQuery {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Parser { input: s }.parse()
+    }
+}
+
+// Writes the query back in the same syntax. Paging is not part of it, and neither are settings left at their defaults.
+impl Display for SearchQuery {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn list<T: Display>(key: &str, values: impl IntoIterator<Item = T>) -> Option<String> {
+            let values = values.into_iter().join(",");
+            (!values.is_empty()).then(|| format!("{}:{}", key, values))
+        }
+
+        let period_range = match (self.period_from, self.period_to) {
+            (None, None) => None,
+            (from, to) => Some(format!("{}-{}", from.unwrap_or(1), to.unwrap_or(4))),
+        };
+
+        let terms = [
+            self.code_pattern.as_ref().map(|p| format!("code:{}", p)),
+            list("points", &self.points),
+            self.points_min.map(|p| format!("points>={}", p)),
+            self.points_max.map(|p| format!("points<={}", p)),
+            list("unit", self.credit_units.iter().map(|u| u.code())),
+            list("pace", self.paces.iter().flatten().map(|p| p.code())),
+            list("-pace", self.exclude_paces.iter().map(|p| p.code())),
+            list("time", self.pace_times.iter().map(|t| t.code())),
+            self.pace_min.map(|p| format!("pace>={}", p)),
+            self.pace_max.map(|p| format!("pace<={}", p)),
+            list("period", self.periods.iter().map(u8::to_string).chain(period_range)),
+            self.term.map(|t| format!("term:{}", t.code())),
+            Some(self.period_select_mode.code())
+                .filter(|&c| c != "only")
+                .map(|c| format!("pmode:{}", c)),
+            list("site", self.sites.iter().map(|s| s.code())),
+            list("-site", self.exclude_sites.iter().map(|s| s.code())),
+            list("lang", self.languages.iter().map(|l| l.code())),
+            list("-lang", self.exclude_languages.iter().map(|l| l.code())),
+            list("diff", self.difficulties.iter().map(|d| d.code())),
+            list("-diff", self.exclude_difficulties.iter().map(|d| d.code())),
+            list("cat", self.categories.iter().map(|c| c.code())),
+            list("-cat", self.exclude_categories.iter().map(|c| c.code())),
+            Some(self.category_select_mode.code())
+                .filter(|&c| c != "any")
+                .map(|c| format!("cmode:{}", c)),
+            Some(self.sort.code())
+                .filter(|&c| c != "code")
+                .map(|c| format!("sort:{}", c)),
+            // Quotes can't be escaped, but they are stripped from name patterns anyway.
+            self.name_pattern.as_ref().map(|p| format!("\"{}\"", p.replace('"', ""))),
+        ];
+
+        write!(f, "{}", terms.into_iter().flatten().join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use courselib::{Category, Pace, Site, Time};
+
+    use super::*;
+
+    fn error_at(input: &str) -> usize {
+        input
+            .parse::<SearchQuery>()
+            .expect_err(&format!("`{}` should not parse", input))
+            .position
+    }
+
+    fn round_trip(query: SearchQuery) {
+        let written = query.to_string();
+        let parsed = written
+            .parse::<SearchQuery>()
+            .unwrap_or_else(|e| panic!("failed to parse `{}`: {}", written, e));
+        assert_eq!(parsed, query, "`{}` parsed differently", written);
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(error_at("site:xx"), 6);
+        assert_eq!(error_at("lang:en points>=x"), 17);
+        assert_eq!(error_at("site:lu,xx"), 9);
+        assert_eq!(error_at("foo:bar"), 1);
+        assert_eq!(error_at("code:"), 6);
+        assert_eq!(error_at("term>=ht"), 5);
+        assert_eq!(error_at("dat \"machine learning"), 5);
+    }
+
+    #[test]
+    fn error_positions_count_characters() {
+        assert_eq!(error_at("åäö site:xx"), 10);
+        assert_eq!(error_at("\"Mätteknik för ingenjörer\" period:5"), 35);
+        assert_eq!(error_at("maskininlärning ökning \"öppen"), 24);
+    }
+
+    #[test]
+    fn negation() {
+        let query = "-site:lu,pt -cat:dat site:st".parse::<SearchQuery>().unwrap();
+        assert_eq!(query.exclude_sites, vec![Site::Lulea, Site::Pitea]);
+        assert_eq!(query.exclude_categories, vec![Category::Data]);
+        assert_eq!(query.sites, vec![Site::Stockholm]);
+
+        let query = "-pace:d50".parse::<SearchQuery>().unwrap();
+        assert_eq!(query.paces, None);
+        assert_eq!(query.exclude_paces, vec![Pace::new(Time::Day, 50.try_into().unwrap())]);
+
+        assert_eq!(error_at("-points:7.5"), 1);
+        assert_eq!(error_at("lang:en -term:ht"), 9);
+    }
+
+    #[test]
+    fn period_ranges() {
+        let query = "period:1,4-2".parse::<SearchQuery>().unwrap();
+        assert_eq!(query.periods, vec![1]);
+        assert_eq!((query.period_from, query.period_to), (Some(4), Some(2)));
+
+        assert_eq!(error_at("period:1-2,3-4"), 12);
+        assert_eq!(error_at("period:2-5"), 10);
+        assert_eq!(error_at("period:0-3"), 8);
+        assert_eq!(error_at("period:1-"), 10);
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(SearchQuery::default());
+        round_trip(SearchQuery {
+            code_pattern: Some("D7*".to_owned()),
+            name_pattern: Some("machine learning".to_owned()),
+            points: vec![7.5, 15.],
+            points_min: Some(1.5),
+            paces: Some(vec![Pace::new(Time::Day, 50.try_into().unwrap())]),
+            exclude_paces: vec![Pace::new(Time::Weekend, 25.try_into().unwrap())],
+            pace_times: vec![Time::Day, Time::Mixed],
+            pace_max: Some(100),
+            periods: vec![1, 3],
+            period_from: Some(4),
+            period_to: Some(2),
+            sites: vec![Site::Lulea],
+            exclude_categories: vec![Category::Data],
+            ..Default::default()
+        });
+
+        for text in ["code:D7* points:7.5,15 period:2,4-1 -site:pt cat:dat \"machine learning\"", "pace>=50 pmode:spans cmode:all sort:name"] {
+            assert_eq!(text.parse::<SearchQuery>().unwrap().to_string(), text);
+        }
+    }
+}
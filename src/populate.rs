@@ -1,5 +1,6 @@
 use std::{collections::HashMap, time::Duration};
 use askama::filters::capitalize;
+use chrono::{NaiveDate, Weekday};
 
 use futures::future::join_all;
 use itertools::Itertools;
@@ -12,7 +13,7 @@ use reqwest::{Client, IntoUrl};
 use sqlx::{Error as SqlxError, PgPool, query};
 use tokio::time::{sleep, timeout};
 
-use courselib::{Course, Difficulty, Language, Pace, Site};
+use courselib::{CHANGES_CHANNEL, Course, COURSE_CODE_PATTERN, CreditUnit, Difficulty, Language, Pace, Site};
 
 const FETCH_TRIES: u8 = 5;
 const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
@@ -21,8 +22,8 @@ const FETCH_RETRY_DELAY: Duration = Duration::from_secs(5);
 lazy_static! {
     static ref CLIENT: Client = Client::new();
     static ref COUNT_PAT: Regex = Regex::new(r"(\d+) träffar").expect("failed to parse regex");
-    static ref POINTS_DIFF_CODE_PAT: Regex = Regex::new(r"(\d+(?:,\d)?) (?:(?:högskole)|(?:förutbildnings))poäng, ([^,]+), ([A-Z][A-Z\d]\d{3}[A-Z])").expect("failed to parse regex");
-    static ref PERIOD_MODULES_PAT: Regex = Regex::new(r"Period ([1-4]) - ([1-4]), v. \d+ \d+ - v. \d+ \d+, (.+)").expect("failed to parse regex");
+    static ref POINTS_DIFF_CODE_PAT: Regex = Regex::new(&format!(r"(\d+(?:,\d)?) ((?:högskole|förutbildnings)poäng), ([^,]+), ({})", COURSE_CODE_PATTERN)).expect("failed to parse regex");
+    static ref PERIOD_MODULES_PAT: Regex = Regex::new(r"Period ([1-4]) - ([1-4]), v. (\d+) (\d+) - v. (\d+) (\d+), (.+)").expect("failed to parse regex");
 }
 
 // This code is allowed to panic since it will only run internally, and we don't want to put incorrect or incomplete data into the database.
@@ -108,12 +109,12 @@ async fn fetch_course_pages() -> impl Iterator<Item = Url> {
 async fn parse_page(url: Url) -> Course {
     let node = parse_html().one(fetch_html(url.clone()).await);
 
-    let (points, difficulty, code) = {
+    let (points, credit_unit, difficulty, code) = {
         let raw = node.select_first(".PT2hF8CC4ZkIu8gQjcXZ")
             .expect("failed to find points, difficulty, code")
             .text_contents();
 
-        let (points, difficulty, code) = POINTS_DIFF_CODE_PAT.captures(&raw)
+        let (points, credit_unit, difficulty, code) = POINTS_DIFF_CODE_PAT.captures(&raw)
             .expect("failed regex match for points, difficulty, code")
             .iter()
             .skip(1)
@@ -126,6 +127,9 @@ async fn parse_page(url: Url) -> Course {
                 .replace(',', ".")
                 .parse()
                 .expect("failed points parse"),
+            credit_unit
+                .parse()
+                .expect("failed credit unit parse"),
             capitalize(difficulty)
                 .unwrap()
                 .parse()
@@ -179,9 +183,9 @@ async fn parse_page(url: Url) -> Course {
         })
         .collect::<HashMap<_, _>>();
 
-    let (period_start, period_end, modules) = {
+    let (period_start, period_end, start_date, end_date, modules) = {
         if let Some(field) = fields.remove("Period") {
-            let (period_start, period_end, modules) = PERIOD_MODULES_PAT.captures(&field)
+            let (period_start, period_end, start_week, start_year, end_week, end_year, modules) = PERIOD_MODULES_PAT.captures(&field)
                 .expect("failed regex match for period, modules")
                 .iter()
                 .skip(1)
@@ -189,6 +193,12 @@ async fn parse_page(url: Url) -> Course {
                 .collect_tuple()
                 .expect("wrong number of items found for period, modules");
 
+            let date = |year: &str, week: &str, weekday| NaiveDate::from_isoywd_opt(
+                year.parse().expect("failed int parse"),
+                week.parse().expect("failed int parse"),
+                weekday
+            ).expect("invalid week");
+
             (
                 Some(period_start
                     .parse()
@@ -196,11 +206,13 @@ async fn parse_page(url: Url) -> Course {
                 Some(period_end
                     .parse()
                     .expect("failed int parse")),
+                Some(date(start_year, start_week, Weekday::Mon)),
+                Some(date(end_year, end_week, Weekday::Sun)),
                 Some(modules
                     .to_owned())
             )
         } else {
-            (None, None, None)
+            (None, None, None, None, None)
         }
     };
 
@@ -219,12 +231,15 @@ async fn parse_page(url: Url) -> Course {
         name_en,
         url,
         points,
+        credit_unit,
         pace,
         prerequisites,
         register_info,
         modules,
         period_start,
         period_end,
+        start_date,
+        end_date,
         site,
         language,
         difficulty,
@@ -252,24 +267,27 @@ macro_rules! destruct_vec {
 async fn insert(courses: Vec<Course>) -> Result<(), SqlxError> {
     let connection = PgPool::connect(include_str!("../connection_string")).await?;
 
-    let (codes, names_se, names_en, urls, points, paces, prerequisites, register_info, modules, period_starts, period_ends, sites, languages, difficulties, conducts) =
-        destruct_vec!(courses, code, name_se, name_en, url, points, pace, prerequisites, register_info, modules, period_start, period_end, site, language, difficulty, conduct);
+    let (codes, names_se, names_en, urls, points, credit_units, paces, prerequisites, register_info, modules, period_starts, period_ends, start_dates, end_dates, sites, languages, difficulties, conducts) =
+        destruct_vec!(courses, code, name_se, name_en, url, points, credit_unit, pace, prerequisites, register_info, modules, period_start, period_end, start_date, end_date, site, language, difficulty, conduct);
 
     // TODO: handle insert conflict
     query!(
-        "INSERT INTO courses (code, name_se, name_en, url, points, pace, prerequisites, register_info, modules, period_start, period_end, site, language, difficulty, conduct)
-        SELECT * FROM UNNEST($1::CHARACTER(6)[], $2::TEXT[], $3::TEXT[], $4::TEXT[], $5::REAL[], $6::pace_enum[], $7::TEXT[], $8::TEXT[], $9::TEXT[], $10::SMALLINT[], $11::SMALLINT[], $12::site_enum[], $13::language_enum[], $14::difficulty_enum[], $15::TEXT[])",
+        "INSERT INTO courses (code, name_se, name_en, url, points, credit_unit, pace, prerequisites, register_info, modules, period_start, period_end, start_date, end_date, site, language, difficulty, conduct)
+        SELECT * FROM UNNEST($1::CHARACTER(6)[], $2::TEXT[], $3::TEXT[], $4::TEXT[], $5::REAL[], $6::credit_unit_enum[], $7::pace_enum[], $8::TEXT[], $9::TEXT[], $10::TEXT[], $11::SMALLINT[], $12::SMALLINT[], $13::DATE[], $14::DATE[], $15::site_enum[], $16::language_enum[], $17::difficulty_enum[], $18::TEXT[])",
         &codes,
         &names_se,
         names_en as Vec<Option<String>>,
         &urls,
         &points,
+        credit_units as Vec<CreditUnit>,
         paces as Vec<Option<Pace>>,
         prerequisites as Vec<Option<String>>,
         register_info as Vec<Option<String>>,
         modules as Vec<Option<String>>,
         period_starts as Vec<Option<i16>>,
         period_ends as Vec<Option<i16>>,
+        start_dates as Vec<Option<NaiveDate>>,
+        end_dates as Vec<Option<NaiveDate>>,
         sites as Vec<Option<Site>>,
         languages as Vec<Option<Language>>,
         difficulties as Vec<Difficulty>,
@@ -278,5 +296,10 @@ async fn insert(courses: Vec<Course>) -> Result<(), SqlxError> {
         .execute(&connection)
         .await?;
 
+    // Lets a running server know that anything it has cached from the table is stale.
+    query!("SELECT pg_notify($1, '')", CHANGES_CHANNEL)
+        .execute(&connection)
+        .await?;
+
     Ok(())
 }